@@ -0,0 +1,203 @@
+//! Authentication as a Github App, via short-lived installation tokens.
+
+use anyhow::anyhow;
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+/// Installation tokens this close to expiry are replaced before use.
+const REFRESH_MARGIN: i64 = 5 * 60;
+
+/// The claims of the JWT that identifies the App itself.
+#[derive(Serialize)]
+struct Claims {
+    iat: i64,
+    exp: i64,
+    iss: String,
+}
+
+/// An installation access token, as handed out by Github.
+#[derive(Deserialize)]
+pub(crate) struct Installation {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// A Github App installation to authenticate as.
+pub struct App {
+    id: u64,
+    installation: u64,
+    key: EncodingKey,
+    current: Mutex<Option<Installation>>,
+}
+
+impl App {
+    /// An App with the given ID, authenticating as one of its installations.
+    /// The private key is the PEM file downloaded from the App's settings.
+    pub fn new(id: u64, installation: u64, pem: &[u8]) -> anyhow::Result<App> {
+        Ok(App {
+            id,
+            installation,
+            key: EncodingKey::from_rsa_pem(pem)?,
+            current: Mutex::new(None),
+        })
+    }
+
+    /// The REST path that exchanges a JWT for an installation token.
+    pub(crate) fn exchange_path(&self) -> String {
+        format!("app/installations/{}/access_tokens", self.installation)
+    }
+
+    /// A JWT signed with the App's private key, valid for a few minutes.
+    fn jwt(&self) -> anyhow::Result<String> {
+        let now = Utc::now().timestamp();
+        let claims = Claims {
+            // Backdated to allow for clock drift, as Github recommends.
+            iat: now - 60,
+            exp: now + 9 * 60,
+            iss: self.id.to_string(),
+        };
+
+        let token = jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, &self.key)?;
+        Ok(token)
+    }
+
+    /// The current installation token. If there isn't one yet, or it's about
+    /// to expire, `exchange` is given a fresh JWT to trade for a new one.
+    pub(crate) fn token<F>(&self, exchange: F) -> anyhow::Result<String>
+    where
+        F: FnOnce(&str) -> anyhow::Result<Installation>,
+    {
+        let mut current = self
+            .current
+            .lock()
+            .map_err(|_| anyhow!("Installation token lock poisoned."))?;
+        let cutoff = Utc::now() + Duration::seconds(REFRESH_MARGIN);
+
+        match current.as_ref() {
+            Some(i) if i.expires_at > cutoff => Ok(i.token.clone()),
+            _ => {
+                let fresh = exchange(&self.jwt()?)?;
+                let token = fresh.token.clone();
+                *current = Some(fresh);
+                Ok(token)
+            }
+        }
+    }
+
+    /// Forget the current installation token, say because Github rejected it.
+    pub(crate) fn invalidate(&self) {
+        if let Ok(mut current) = self.current.lock() {
+            *current = None;
+        }
+    }
+}
+
+#[test]
+fn installation_tokens() {
+    use crate::github::Client;
+    use crate::transport::Curl;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/api/graphql", listener.local_addr().unwrap());
+    let seen = Arc::new(Mutex::new(Vec::new()));
+
+    // A stand-in for Github: two token exchanges, the first of which yields a
+    // token that is already due for renewal, and three queries.
+    let server = {
+        let seen = seen.clone();
+        std::thread::spawn(move || {
+            let soon = Utc::now() + Duration::seconds(60);
+            let later = Utc::now() + Duration::hours(1);
+            let mut tokens = vec![("ghs_second", later), ("ghs_first", soon)];
+
+            for stream in listener.incoming().take(5) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let path = request.split(' ').nth(1).unwrap().to_string();
+
+                let mut auth = String::new();
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim();
+                    if line.is_empty() {
+                        break;
+                    }
+                    let (k, v) = line.split_once(':').unwrap();
+                    match k.to_lowercase().as_str() {
+                        "authorization" => auth = v.trim().to_string(),
+                        "content-length" => length = v.trim().parse().unwrap(),
+                        _ => {}
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                let (status, reply) = if path.ends_with("/access_tokens") {
+                    let (token, expiry) = tokens.pop().unwrap();
+                    let reply = format!(
+                        r#"{{"token": "{}", "expires_at": "{}"}}"#,
+                        token,
+                        expiry.to_rfc3339()
+                    );
+                    ("201 Created", reply)
+                } else {
+                    let reply = r#"{"data": {"rateLimit":
+                      {"limit": 5000, "remaining": 4999, "resetAt": "2021-01-01T01:00:00Z"}}}"#;
+                    ("200 OK", reply.to_string())
+                };
+
+                seen.lock().unwrap().push((path, auth));
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    reply.len(),
+                    reply
+                )
+                .unwrap();
+            }
+        })
+    };
+
+    let pem = include_bytes!("../testdata/app.pem");
+    let app = App::new(42, 7, pem).unwrap();
+    let client = Client::as_app(&url, app).with_transport(Curl::default());
+    for _ in 0..3 {
+        crate::rate_limit(&client).unwrap();
+    }
+    server.join().unwrap();
+
+    let seen = seen.lock().unwrap();
+    let paths: Vec<&str> = seen.iter().map(|(p, _)| p.as_str()).collect();
+    let auths: Vec<&str> = seen.iter().map(|(_, a)| a.as_str()).collect();
+    assert_eq!(
+        vec![
+            "/api/v3/app/installations/7/access_tokens",
+            "/api/graphql",
+            "/api/v3/app/installations/7/access_tokens",
+            "/api/graphql",
+            "/api/graphql",
+        ],
+        paths
+    );
+    assert_eq!("bearer ghs_first", auths[1]);
+    assert_eq!("bearer ghs_second", auths[3]);
+    assert_eq!("bearer ghs_second", auths[4]);
+
+    // The exchange itself is authorised by a JWT naming the App.
+    let jwt = auths[0].strip_prefix("Bearer ").unwrap();
+    let public = include_bytes!("../testdata/app.pub.pem");
+    let key = jsonwebtoken::DecodingKey::from_rsa_pem(public).unwrap();
+    let mut validation = jsonwebtoken::Validation::new(Algorithm::RS256);
+    validation.set_issuer(&["42"]);
+    jsonwebtoken::decode::<serde_json::Value>(jwt, &key, &validation).unwrap();
+}
@@ -0,0 +1,152 @@
+//! An on-disk cache of successful Github responses.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// How long cached responses are trusted by default.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Successful responses, keyed by the endpoint and full body of the request
+/// that produced them. Since the body includes any paging cursor, every page of
+/// a query is cached separately.
+#[derive(Clone)]
+pub struct Cache {
+    dir: PathBuf,
+    ttl: Duration,
+    /// Ignore existing entries, but still write new ones.
+    refresh: bool,
+}
+
+/// The size of a [`Cache`](struct.Cache.html) on disk.
+#[derive(Debug, Default)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub expired: usize,
+    pub bytes: u64,
+}
+
+impl Cache {
+    /// A cache kept in the given directory, which is created as needed.
+    pub fn new(dir: PathBuf, ttl: Duration) -> Cache {
+        Cache {
+            dir,
+            ttl,
+            refresh: false,
+        }
+    }
+
+    /// Don't read any existing entries, but overwrite them with fresh
+    /// responses.
+    pub fn refreshing(self) -> Cache {
+        Cache {
+            refresh: true,
+            ..self
+        }
+    }
+
+    /// The directory this cache lives in.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, url: &str, body: &str) -> PathBuf {
+        let key = fingerprint(&[url.as_bytes(), body.as_bytes()]);
+        self.dir.join(format!("{:016x}.json", key))
+    }
+
+    fn is_expired(&self, path: &Path) -> bool {
+        fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| SystemTime::now().duration_since(t).ok())
+            .map(|age| age > self.ttl)
+            .unwrap_or(true)
+    }
+
+    /// A still-fresh response to the given request, if there is one.
+    pub fn get(&self, url: &str, body: &str) -> Option<Vec<u8>> {
+        let path = self.path(url, body);
+        if self.refresh || self.is_expired(&path) {
+            None
+        } else {
+            fs::read(path).ok()
+        }
+    }
+
+    /// Remember the response to some request.
+    pub fn put(&self, url: &str, body: &str, response: &[u8]) -> anyhow::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(url, body);
+        // Written aside and then moved, so that concurrent readers never see
+        // a half-written entry.
+        let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&tmp, response)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    fn entries(&self) -> Vec<PathBuf> {
+        fs::read_dir(&self.dir)
+            .map(|rd| {
+                rd.filter_map(|e| e.ok())
+                    .map(|e| e.path())
+                    .filter(|p| p.extension().map(|e| e == "json").unwrap_or(false))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// How much is stored, and how much of it is stale?
+    pub fn stats(&self) -> CacheStats {
+        self.entries()
+            .iter()
+            .fold(CacheStats::default(), |mut stats, path| {
+                stats.entries += 1;
+                stats.bytes += fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+                if self.is_expired(path) {
+                    stats.expired += 1;
+                }
+                stats
+            })
+    }
+
+    /// Delete entries from the cache, either all of them or only those that
+    /// have expired. Yields the number of entries removed.
+    pub fn clear(&self, only_expired: bool) -> anyhow::Result<usize> {
+        let mut removed = 0;
+        for path in self.entries() {
+            if !only_expired || self.is_expired(&path) {
+                fs::remove_file(path)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// A stable 64-bit FNV-1a hash of some byte strings.
+pub(crate) fn fingerprint(parts: &[&[u8]]) -> u64 {
+    parts
+        .iter()
+        .flat_map(|p| p.iter().chain(std::iter::once(&0)))
+        .fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+            (hash ^ *b as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+#[test]
+fn cache_roundtrip() {
+    let dir = std::env::temp_dir().join(format!("credit-cache-test-{}", std::process::id()));
+    let cache = Cache::new(dir.clone(), DEFAULT_TTL);
+
+    assert!(cache.get("url", "query").is_none());
+    cache.put("url", "query", b"{}").unwrap();
+    assert_eq!(Some(b"{}".to_vec()), cache.get("url", "query"));
+    assert!(cache.get("other", "query").is_none());
+    assert!(cache.clone().refreshing().get("url", "query").is_none());
+    assert_eq!(1, cache.stats().entries);
+    assert_eq!(1, cache.clear(false).unwrap());
+
+    fs::remove_dir_all(dir).unwrap();
+}
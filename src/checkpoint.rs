@@ -0,0 +1,277 @@
+//! Progress through a long crawl, saved so that it can be resumed.
+
+use crate::repo::Issue;
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// One line of a checkpoint file. Lines are appended as a crawl progresses, so
+/// an interruption loses at most the page in flight. Threads are borrowed when
+/// writing, and owned when reading.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum Entry<T> {
+    /// The crawl was split into date shards.
+    Plan {
+        from: DateTime<Utc>,
+        until: DateTime<Utc>,
+        shards: usize,
+    },
+    /// A page of a sequential crawl, and the cursor that follows it.
+    Page { cursor: Option<String>, threads: T },
+    /// Every thread of a single date shard.
+    Shard {
+        from: DateTime<Utc>,
+        until: DateTime<Utc>,
+        threads: T,
+    },
+}
+
+/// The start and (exclusive) end of a date shard.
+type Bounds = (DateTime<Utc>, DateTime<Utc>);
+
+/// What a previous, unfinished crawl got through.
+#[derive(Default)]
+pub(crate) struct Resumed {
+    /// How the crawl was sharded, if it was.
+    pub plan: Option<(DateTime<Utc>, DateTime<Utc>, usize)>,
+    /// Where a sequential crawl left off.
+    pub cursor: Option<String>,
+    /// The threads of a sequential crawl so far.
+    pub threads: Vec<Issue>,
+}
+
+/// The saved progress of fetching one kind of thread from one repository.
+pub(crate) struct Checkpoint {
+    path: PathBuf,
+    file: Mutex<File>,
+    /// Finished shards from a previous crawl, keyed by their bounds.
+    shards: Mutex<HashMap<Bounds, Vec<Issue>>>,
+}
+
+impl Checkpoint {
+    /// Open the checkpoint at the given path. Unless resuming, any progress
+    /// saved there is discarded.
+    pub fn open(path: PathBuf, resume: bool) -> anyhow::Result<(Checkpoint, Resumed)> {
+        let mut resumed = Resumed::default();
+        let mut shards = HashMap::new();
+        // The length of the file up to its last complete entry.
+        let mut valid = 0;
+
+        if resume && path.exists() {
+            let saved = fs::read_to_string(&path)?;
+            // A line cut short by a crash is dropped, along with anything
+            // after it.
+            let entries = saved
+                .split_inclusive('\n')
+                .filter(|l| l.ends_with('\n'))
+                .map_while(|l| {
+                    serde_json::from_str::<Entry<Vec<Issue>>>(l)
+                        .ok()
+                        .map(|e| (l.len(), e))
+                });
+
+            for (len, entry) in entries {
+                valid += len as u64;
+                match entry {
+                    Entry::Plan {
+                        from,
+                        until,
+                        shards,
+                    } => resumed.plan = Some((from, until, shards)),
+                    Entry::Page { cursor, threads } => {
+                        resumed.cursor = cursor;
+                        resumed.threads.extend(threads);
+                    }
+                    Entry::Shard {
+                        from,
+                        until,
+                        threads,
+                    } => {
+                        shards.insert((from, until), threads);
+                    }
+                }
+            }
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Couldn't open checkpoint {}", path.display()))?;
+        file.set_len(valid)?;
+
+        let checkpoint = Checkpoint {
+            path,
+            file: Mutex::new(file),
+            shards: Mutex::new(shards),
+        };
+
+        Ok((checkpoint, resumed))
+    }
+
+    /// The path of the checkpoint for some repository and kind of thread,
+    /// crawled up to `end`. Crawls up to different ends are planned
+    /// differently, so each has its own. `--start` only filters what was
+    /// fetched, and so needn't be part of it.
+    pub fn path(
+        dir: &Path,
+        host: &str,
+        owner: &str,
+        repo: &str,
+        kind: &str,
+        end: &Option<DateTime<Utc>>,
+    ) -> PathBuf {
+        let name = match end {
+            None => format!("{}.jsonl", kind),
+            Some(end) => format!("{}-until-{}.jsonl", kind, end.format("%Y%m%dT%H%M%SZ")),
+        };
+        dir.join(host).join(owner).join(repo).join(name)
+    }
+
+    fn write(&self, entry: &Entry<&[Issue]>) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| anyhow!("Checkpoint lock poisoned."))?;
+        file.write_all(&line)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Note how the crawl has been split into shards.
+    pub fn plan(
+        &self,
+        from: DateTime<Utc>,
+        until: DateTime<Utc>,
+        shards: usize,
+    ) -> anyhow::Result<()> {
+        self.write(&Entry::Plan {
+            from,
+            until,
+            shards,
+        })
+    }
+
+    /// Save a page of a sequential crawl, and the cursor that follows it.
+    pub fn page(&self, cursor: Option<&str>, threads: &[Issue]) -> anyhow::Result<()> {
+        self.write(&Entry::Page {
+            cursor: cursor.map(|c| c.to_string()),
+            threads,
+        })
+    }
+
+    /// Save a finished shard.
+    pub fn shard(
+        &self,
+        from: DateTime<Utc>,
+        until: DateTime<Utc>,
+        threads: &[Issue],
+    ) -> anyhow::Result<()> {
+        self.write(&Entry::Shard {
+            from,
+            until,
+            threads,
+        })
+    }
+
+    /// The threads of a shard finished by a previous crawl, if any.
+    pub fn finished(&self, from: DateTime<Utc>, until: DateTime<Utc>) -> Option<Vec<Issue>> {
+        self.shards.lock().ok()?.remove(&(from, until))
+    }
+
+    /// The crawl is complete, so its checkpoint is no longer needed.
+    pub fn finish(self) -> anyhow::Result<()> {
+        drop(self.file);
+        fs::remove_file(&self.path)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn resume_after_failure() {
+    use crate::fixtures;
+    use crate::github::Client;
+    use crate::repo::{issues, Mode};
+    use crate::transport::Fixtures;
+    use indicatif::ProgressBar;
+
+    let page = |n: u32, next: Option<&str>| {
+        let created = "2020-01-01T00:00:00Z";
+        fixtures::page("issues", next, &[fixtures::thread(n, created, created)])
+    };
+    let dir = std::env::temp_dir().join(format!("credit-checkpoint-test-{}", std::process::id()));
+    let path = Checkpoint::path(&dir, "api.github.com", "a", "b", "issues", &None);
+
+    // The second page never arrives.
+    let fixtures = Fixtures::new()
+        .respond("issues(first: 1)", fixtures::sizes(2, 0))
+        .respond("issues(", page(1, Some("a")));
+    let client = Client::github("fake")
+        .with_transport(fixtures)
+        .with_checkpoints(dir.clone(), false);
+    assert!(issues(
+        &client,
+        &None,
+        &Mode::Issues,
+        "a",
+        "b",
+        &ProgressBar::hidden()
+    )
+    .is_err());
+    assert!(path.exists());
+
+    // A crawl up to a different end doesn't pick up where that one left off.
+    let fixtures = Fixtures::new()
+        .respond("issues(first: 1)", fixtures::sizes(1, 0))
+        .respond("issues(", page(7, None));
+    let client = Client::github("fake")
+        .with_transport(fixtures)
+        .with_checkpoints(dir.clone(), true);
+    let end = "2021-01-01T00:00:00Z".parse().ok();
+    let numbers: Vec<u32> = issues(
+        &client,
+        &end,
+        &Mode::Issues,
+        "a",
+        "b",
+        &ProgressBar::hidden(),
+    )
+    .unwrap()
+    .iter()
+    .map(|i| i.number)
+    .collect();
+    assert_eq!(vec![7], numbers);
+    assert!(path.exists());
+
+    let fixtures = Fixtures::new().respond("\"after\":\"a\"", page(2, None));
+    let client = Client::github("fake")
+        .with_transport(fixtures)
+        .with_checkpoints(dir.clone(), true);
+    let numbers: Vec<u32> = issues(
+        &client,
+        &None,
+        &Mode::Issues,
+        "a",
+        "b",
+        &ProgressBar::hidden(),
+    )
+    .unwrap()
+    .iter()
+    .map(|i| i.number)
+    .collect();
+    assert_eq!(vec![1, 2], numbers);
+    assert!(!path.exists());
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -1,19 +1,21 @@
 //! Types and functions for the `users` command.
 
 use crate::github;
+use anyhow::anyhow;
 use indicatif::ProgressBar;
 use serde::Deserialize;
-use std::thread;
-use std::time::Duration;
+use serde_json::json;
+use std::io::IsTerminal;
 
-/// The maximum number of results to fetch in a page.
+/// How many users to ask for in a page, until Github's timeouts suggest
+/// otherwise. Their contributions are expensive to count.
 const PAGE_SIZE: u32 = 5;
 
-/// The maximum number of pages to pull when querying for user contributions.
-const MAX_PAGES: u32 = 10 * (100 / PAGE_SIZE);
+/// The most users Github will give in a single page.
+const MAX_PAGE_SIZE: u32 = 100;
 
-/// Only attempt to query for a page this many times.
-const MAX_ATTEMPTS: u32 = 10;
+/// The most users a search will yield.
+const MAX_USERS: usize = 1000;
 
 #[derive(Deserialize)]
 struct SearchQuery {
@@ -69,115 +71,144 @@ pub struct UserCount {
     pub user_count: u32,
 }
 
-fn user_count_query(location: &str) -> String {
-    format!(
-        "{{ \
-         \"query\": \"{{ \
-             search(type: USER, query: \\\"type:user location:{}\\\") {{ \
-               userCount \
-             }} \
-           }}\" \
-         }}",
-        location,
-    )
+const USER_COUNT_QUERY: &str = "
+query($q: String!) {
+  rateLimit {
+    cost
+    remaining
+    resetAt
+  }
+  search(type: USER, query: $q) {
+    userCount
+  }
+}";
+
+const USERS_QUERY: &str = "
+query($q: String!, $first: Int!, $after: String) {
+  rateLimit {
+    cost
+    remaining
+    resetAt
+  }
+  search(type: USER, query: $q, first: $first, after: $after) {
+    pageInfo {
+      hasNextPage
+      endCursor
+    }
+    edges {
+      node {
+        ... on User {
+          login
+          name
+          followers {
+            totalCount
+          }
+          contributionsCollection {
+            contributionCalendar {
+              totalContributions
+            }
+            restrictedContributionsCount
+          }
+        }
+      }
+    }
+  }
+}";
+
+/// A search for users in some location. Quoted, so that locations with spaces
+/// are matched in full. Github's search has no way to escape a quote within
+/// quotes, so locations containing one are refused rather than altered.
+fn location_search(location: &str) -> anyhow::Result<String> {
+    if location.contains('"') {
+        return Err(anyhow!("Locations can't contain quotes: {}", location));
+    }
+
+    Ok(format!("type:user location:\"{}\"", location))
 }
 
-fn users_query(location: &str, page: Option<&str>) -> String {
-    format!(
-        "{{ \
-         \"query\": \"{{ \
-         search(type: USER, query: \\\"type:user location:{} sort:followers-desc\\\", first: {}{}) {{ \
-           pageInfo {{ \
-             hasNextPage \
-             endCursor \
-           }} \
-           edges {{ \
-             node {{ \
-               ... on User {{ \
-                 login \
-                 name \
-                 followers {{ \
-                   totalCount \
-                 }} \
-                 contributionsCollection {{ \
-                   contributionCalendar {{ \
-                     totalContributions \
-                   }} \
-                   restrictedContributionsCount \
-                 }} \
-               }} \
-             }} \
-           }} \
-         }} \
-       }}\" \
-    }}",
-        location,
-        PAGE_SIZE,
-        page.map(|p| format!(", after: \\\"{}\\\"", p))
-            .unwrap_or_else(|| "".to_string()),
+fn user_count_query(search: &str) -> github::Request {
+    github::Request::new(USER_COUNT_QUERY, json!({ "q": search }))
+}
+
+fn users_query(search: &str, page: Option<&str>, first: u32) -> github::Request {
+    github::Request::new(
+        USERS_QUERY,
+        json!({
+            "q": format!("{} sort:followers-desc", search),
+            "first": first,
+            "after": page,
+        }),
     )
 }
 
 /// How many users claim to be from a certain area?
-pub fn user_count(token: &str, location: &str) -> anyhow::Result<UserCount> {
-    let body = user_count_query(location);
-    let result: UserCountQuery = github::lookup(token, body)?;
+pub fn user_count(client: &github::Client, location: &str) -> anyhow::Result<UserCount> {
+    let request = user_count_query(&location_search(location)?);
+    let result: UserCountQuery = github::lookup(client, &request)?;
     Ok(result.search)
 }
 
-/// Produce a list of Github Users, ordered by their contribution counts.
-pub fn user_contributions(token: &str, location: &str) -> anyhow::Result<Vec<UserContribs>> {
-    eprintln!("Fetching data pages from Github...");
-    let progress = ProgressBar::new(MAX_PAGES as u64);
-    let result = user_contributions_work(token, &progress, location, None, 1, 1);
-    progress.finish_and_clear();
-    result
+/// Github Users in some area, most followed first, a page at a time.
+pub fn user_pages<'a>(
+    client: &'a github::Client,
+    location: &str,
+) -> anyhow::Result<github::Pages<'a, UserContribs>> {
+    let search = location_search(location)?;
+    Ok(github::Pages::new(
+        client,
+        "users",
+        (PAGE_SIZE, MAX_PAGE_SIZE),
+        move |cursor, first| users_query(&search, cursor, first),
+        |result: SearchQuery| result.search,
+    ))
 }
 
-fn user_contributions_work(
-    token: &str,
-    progress: &ProgressBar,
+/// Produce a list of Github Users, ordered by their contribution counts.
+pub fn user_contributions(
+    client: &github::Client,
     location: &str,
-    page: Option<&str>,
-    page_num: u32,
-    attempts: u32,
 ) -> anyhow::Result<Vec<UserContribs>> {
-    let body = users_query(location, page);
-    match github::lookup::<SearchQuery>(token, body) {
-        Err(_) if attempts < MAX_ATTEMPTS => {
-            thread::sleep(Duration::from_secs(10));
-            user_contributions_work(token, progress, location, page, page_num, attempts + 1)
-        }
-        Err(e) => Err(e),
-        Ok(result) => {
-            progress.inc(1);
-            let page = result.search;
-            let info = page.page_info;
-            let mut users: Vec<UserContribs> = page.edges.into_iter().map(|n| n.node).collect();
-
-            match info.end_cursor {
-                // Ends early if we've found users with 0 followers.
-                Some(c)
-                    if info.has_next_page
-                        && page_num < MAX_PAGES
-                        && users
-                            .last()
-                            .map(|uc| uc.followers.total_count > 0)
-                            .unwrap_or(false) =>
-                {
-                    let mut next = user_contributions_work(
-                        token,
-                        progress,
-                        location,
-                        Some(&c),
-                        page_num + 1,
-                        1,
-                    )?;
-                    users.append(&mut next);
-                    Ok(users)
-                }
-                _ => Ok(users),
-            }
+    // Progress bars hide themselves when stderr isn't a terminal, so this
+    // should too.
+    if std::io::stderr().is_terminal() {
+        eprintln!("Fetching data pages from Github...");
+    }
+    let progress = ProgressBar::new(MAX_USERS as u64);
+    let mut users = Vec::new();
+
+    for page in user_pages(client, location)? {
+        let page = page?;
+        progress.inc(page.edges.len() as u64);
+        users.extend(page.edges.into_iter().map(|n| n.node));
+
+        // Ends early if we've found users with 0 followers.
+        if users.len() >= MAX_USERS
+            || users
+                .last()
+                .map(|uc| uc.followers.total_count == 0)
+                .unwrap_or(true)
+        {
+            break;
         }
     }
+
+    progress.finish_and_clear();
+    Ok(users)
+}
+
+#[test]
+fn awkward_locations() {
+    let search = location_search("São Paulo").unwrap();
+    let request = users_query(&search, Some("abc=="), 5);
+    let body = serde_json::to_string(&request).unwrap();
+    let parsed: serde_json::Value = serde_json::from_str(&body).unwrap();
+
+    assert_eq!(
+        "type:user location:\"São Paulo\" sort:followers-desc",
+        parsed["variables"]["q"]
+    );
+    assert_eq!("abc==", parsed["variables"]["after"]);
+
+    // Quotes would end the search term early.
+    assert!(location_search("São \"Paulo\"").is_err());
 }
@@ -0,0 +1,196 @@
+//! Accounting of the API points spent by queries.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The `rateLimit` field requested alongside paged queries.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RateCost {
+    pub cost: u32,
+    pub remaining: u32,
+    pub reset_at: DateTime<Utc>,
+}
+
+/// The API points Github charges for a query it counts as this many requests,
+/// per its documented formula. See
+/// https://docs.github.com/en/graphql/overview/resource-limitations
+pub(crate) fn points(requests: u32) -> u32 {
+    ((requests + 50) / 100).max(1)
+}
+
+/// Lists that aren't paged, and so take no `first` argument, but whose length
+/// still multiplies the requests of the connections within them. There's a
+/// reaction group for each kind of reaction.
+const LISTS: &[(&str, u32)] = &[("reactionGroups", 8)];
+
+/// How many requests Github's cost formula counts a query as, going by its
+/// shape: each connection is one request for every time its parent is fetched,
+/// which is the product of the `first` (or `last`) limits of the connections
+/// it's nested in. A `$first` variable stands for `page`.
+pub(crate) fn requests(query: &str, page: u32) -> u32 {
+    let mut total = 0;
+    // How many times the selection currently open is fetched.
+    let mut fetched = vec![1];
+    // The field about to open a selection, and its arguments.
+    let mut field = String::new();
+    let mut args = String::new();
+    let mut in_word = false;
+    let mut chars = query.chars();
+
+    while let Some(c) = chars.next() {
+        let is_word = c.is_alphanumeric() || c == '_';
+        if is_word && !in_word {
+            field.clear();
+            args.clear();
+        }
+        in_word = is_word;
+
+        match c {
+            c if is_word => field.push(c),
+            '(' => args = chars.by_ref().take_while(|&c| c != ')').collect(),
+            '{' => {
+                let parent = fetched.last().copied().unwrap_or(1);
+                let list = LISTS.iter().find(|(name, _)| *name == field);
+                let inner = match (limit(&args, page), list) {
+                    (Some(limit), _) => {
+                        total += parent;
+                        parent * limit
+                    }
+                    (None, Some((_, length))) => parent * length,
+                    (None, None) => parent,
+                };
+                fetched.push(inner);
+            }
+            '}' => {
+                fetched.pop();
+            }
+            _ => {}
+        }
+    }
+
+    total
+}
+
+/// The `first` or `last` argument among those of a field, if it has one.
+fn limit(args: &str, page: u32) -> Option<u32> {
+    args.split(',')
+        .filter_map(|arg| arg.split_once(':'))
+        .find(|(name, _)| matches!(name.trim(), "first" | "last"))
+        .and_then(|(_, value)| match value.trim() {
+            "$first" => Some(page),
+            n => n.parse().ok(),
+        })
+}
+
+/// The API points spent by some set of requests.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Cost {
+    pub points: u32,
+    pub requests: u32,
+    /// The cost of the most expensive single request.
+    pub largest: u32,
+    /// What remained of the token's quota after the latest request.
+    pub remaining: Option<u32>,
+    /// When the token's quota will next reset.
+    pub reset_at: Option<DateTime<Utc>>,
+    /// Was fetching cut short to stay within a budget?
+    #[serde(default)]
+    pub over_budget: bool,
+}
+
+impl Cost {
+    pub(crate) fn charge(&mut self, rc: &RateCost) {
+        self.points += rc.cost;
+        self.requests += 1;
+        self.largest = self.largest.max(rc.cost);
+        self.remaining = Some(rc.remaining);
+        self.reset_at = Some(rc.reset_at);
+    }
+}
+
+impl fmt::Display for Cost {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} points over {} requests", self.points, self.requests)?;
+        if let (Some(remaining), Some(reset)) = (self.remaining, self.reset_at) {
+            write!(
+                f,
+                " ({} remaining, resets at {})",
+                remaining,
+                reset.format("%H:%M UTC")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The error given in place of a request that would exceed the budget set by
+/// [`Client::with_budget`](struct.Client.html#method.with_budget).
+#[derive(Debug)]
+pub struct OverBudget {
+    pub budget: u32,
+}
+
+impl fmt::Display for OverBudget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "The budget of {} API points has been reached.",
+            self.budget
+        )
+    }
+}
+
+impl std::error::Error for OverBudget {}
+
+#[test]
+fn query_requests() {
+    // The example from Github's documentation, which it says costs 51 points.
+    let query = "
+query {
+  viewer {
+    login
+    repositories(first: 100) {
+      edges {
+        node {
+          id
+          issues(first: 50) {
+            edges {
+              node {
+                id
+                labels(first: 60) {
+                  edges {
+                    node {
+                      id
+                      name
+                    }
+                  }
+                }
+              }
+            }
+          }
+        }
+      }
+    }
+  }
+}";
+    assert_eq!(1 + 100 + 100 * 50, requests(query, 0));
+    assert_eq!(51, points(requests(query, 0)));
+
+    let paged = "
+query($first: Int!, $after: String) {
+  search(query: \"a\", first: $first, after: $after) {
+    edges {
+      node {
+        reactionGroups {
+          reactors(first: 10) {
+            totalCount
+          }
+        }
+      }
+    }
+  }
+}";
+    assert_eq!(1 + 20 * 8, requests(paged, 20));
+}
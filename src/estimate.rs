@@ -0,0 +1,190 @@
+//! Types and functions for the `estimate` command.
+
+use crate::cost;
+use crate::github;
+use crate::limit::{rate_limit, RateLimit};
+use crate::repo::{pages_needed, sizes, Mode, PAGE_SIZE};
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::fmt;
+
+/// A typical time for Github to answer a full page of threads and their
+/// comments.
+const PAGE_LATENCY: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// What crawling a single repository should take.
+#[derive(Serialize)]
+pub struct RepoEstimate {
+    pub name: String,
+    pub issues: usize,
+    pub prs: usize,
+    pub pages: usize,
+    pub points: u32,
+}
+
+/// What a run of `credit repo` should take, before actually making it.
+#[derive(Serialize)]
+pub struct Estimate {
+    pub repos: Vec<RepoEstimate>,
+    /// The quota of the token the run would start with.
+    pub limit: RateLimit,
+    /// How long the run should take, including any wait for the quota to
+    /// reset.
+    pub seconds: i64,
+}
+
+impl Estimate {
+    /// The pages of threads to be fetched across every repository.
+    pub fn pages(&self) -> usize {
+        self.repos.iter().map(|r| r.pages).sum()
+    }
+
+    /// The API points to be spent across every repository.
+    pub fn points(&self) -> u32 {
+        self.repos.iter().map(|r| r.points).sum()
+    }
+
+    /// Would the run spend more than what remains of the quota?
+    pub fn exceeds_quota(&self) -> bool {
+        self.points() > self.limit.remaining
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "| Repository | Issues | PRs | Pages | API Points |")?;
+        writeln!(f, "| --- | --- | --- | --- | --- |")?;
+        for r in self.repos.iter() {
+            writeln!(
+                f,
+                "| {} | {} | {} | {} | {} |",
+                r.name, r.issues, r.prs, r.pages, r.points
+            )?;
+        }
+
+        write!(
+            f,
+            "\n{} pages for about {} API points, taking roughly {}. ",
+            self.pages(),
+            self.points(),
+            humanize(self.seconds)
+        )?;
+        write!(
+            f,
+            "{} of {} points remain, resetting at {}.",
+            self.limit.remaining,
+            self.limit.limit,
+            self.limit.reset_at.format("%H:%M UTC")
+        )?;
+
+        if self.exceeds_quota() {
+            write!(
+                f,
+                "\n\n> **Warning:** This exceeds the remaining quota, so the run \
+                 will have to wait for it to reset. Consider `--max-cost` or more \
+                 tokens."
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A duration in the largest units that make sense for it.
+fn humanize(seconds: i64) -> String {
+    match seconds {
+        s if s < 60 => format!("{} seconds", s),
+        s if s < 60 * 60 => format!("{} minutes", s / 60),
+        s => format!("{}h {}m", s / 3600, (s % 3600) / 60),
+    }
+}
+
+/// Predict the cost and duration of fetching the Issues and PRs of the given
+/// repositories, from how many of each they have.
+pub fn estimate(
+    client: &github::Client,
+    repos: &[(String, String)],
+    commits: bool,
+) -> anyhow::Result<Estimate> {
+    let prs = if commits {
+        Mode::PRsWithCommits
+    } else {
+        Mode::PRs
+    };
+    let issue_points = cost::points(Mode::Issues.page_requests(PAGE_SIZE));
+    let pr_points = cost::points(prs.page_requests(PAGE_SIZE));
+
+    let repos = repos
+        .par_iter()
+        .map(|(owner, repo)| {
+            let counts =
+                sizes(client, owner, repo).with_context(|| format!("{}/{}", owner, repo))?;
+            let issue_pages = pages_needed(counts.issues.total_count);
+            let pr_pages = pages_needed(counts.pull_requests.total_count);
+
+            Ok(RepoEstimate {
+                name: format!("{}/{}", owner, repo),
+                issues: counts.issues.total_count,
+                prs: counts.pull_requests.total_count,
+                pages: issue_pages + pr_pages,
+                points: issue_pages as u32 * issue_points + pr_pages as u32 * pr_points,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let limit = rate_limit(client)?;
+    let pages: usize = repos.iter().map(|r| r.pages).sum();
+    let points: u32 = repos.iter().map(|r| r.points).sum();
+    let mut duration = client
+        .scheduler()
+        .duration(pages as u32, PAGE_LATENCY)
+        .as_secs() as i64;
+
+    // Past the remaining quota, the run waits for it to reset, perhaps more
+    // than once.
+    if points > limit.remaining {
+        let resets = (points - limit.remaining).div_ceil(limit.limit.max(1)) as i64;
+        let first = (limit.reset_at - Utc::now()).max(Duration::zero());
+        duration += first.num_seconds() + (resets - 1) * 60 * 60;
+    }
+
+    Ok(Estimate {
+        repos,
+        limit,
+        seconds: duration,
+    })
+}
+
+#[test]
+fn over_quota() {
+    use crate::fixtures;
+    use crate::transport::Fixtures;
+
+    let reset = (Utc::now() + Duration::minutes(30)).to_rfc3339();
+    let limit = format!(
+        r#"{{"data": {{"rateLimit": {{"limit": 5000, "remaining": 100, "resetAt": "{}"}}}}}}"#,
+        reset
+    );
+    let fixtures = Fixtures::new()
+        .respond("\"name\":\"small\"", fixtures::sizes(150, 0))
+        .respond("\"name\":\"large\"", fixtures::sizes(9_000, 1_000))
+        .respond("rateLimit {\\n    limit", limit);
+    let client = github::Client::github("fake").with_transport(fixtures);
+    let repos = vec![
+        ("a".to_string(), "small".to_string()),
+        ("a".to_string(), "large".to_string()),
+    ];
+    let est = estimate(&client, &repos, true).unwrap();
+
+    // Two pages of Issues, and an empty page of PRs.
+    let issue_page = cost::points(Mode::Issues.page_requests(PAGE_SIZE));
+    let pr_page = cost::points(Mode::PRsWithCommits.page_requests(PAGE_SIZE));
+    assert_eq!(3, est.repos[0].pages);
+    assert_eq!(2 * issue_page + pr_page, est.repos[0].points);
+    // Large enough to be sharded.
+    assert_eq!(90 + 18 + 10, est.repos[1].pages);
+    assert!(est.exceeds_quota());
+    assert!(est.seconds >= 29 * 60);
+}
@@ -0,0 +1,59 @@
+//! Canned Github responses shared by the tests of several modules.
+
+use serde_json::{json, Value};
+
+/// An Issue or Pull Request without comments, as found among the edges of a
+/// page.
+pub(crate) fn thread(number: u32, created: &str, updated: &str) -> Value {
+    json!({"node": {
+        "number": number,
+        "author": null,
+        "createdAt": created,
+        "updatedAt": updated,
+        "closedAt": null,
+        "comments": {"edges": []}
+    }})
+}
+
+/// A page of `issues` or `pullRequests`, followed by another at `next` if
+/// given.
+pub(crate) fn page(kind: &str, next: Option<&str>, threads: &[Value]) -> String {
+    json!({"data": {"repository": connection(kind, next, threads)}}).to_string()
+}
+
+/// Like [`page`], but also reporting what it cost.
+pub(crate) fn costly_page(
+    points: u32,
+    kind: &str,
+    next: Option<&str>,
+    threads: &[Value],
+) -> String {
+    json!({"data": {
+        "rateLimit": {"cost": points, "remaining": 4990, "resetAt": "2021-01-01T01:00:00Z"},
+        "repository": connection(kind, next, threads)
+    }})
+    .to_string()
+}
+
+fn connection(kind: &str, next: Option<&str>, threads: &[Value]) -> Value {
+    json!({kind: {
+        "pageInfo": {"hasNextPage": next.is_some(), "endCursor": next},
+        "edges": threads
+    }})
+}
+
+/// How many Issues and PRs a repository has, the oldest of each opened at the
+/// start of 2020 and the newest on the 6th of January.
+pub(crate) fn sizes(issues: usize, prs: usize) -> String {
+    let size = |count: usize, opened: &str| {
+        let thread = (count > 0).then(|| thread(1, opened, opened));
+        json!({"totalCount": count, "edges": thread.into_iter().collect::<Vec<_>>()})
+    };
+    json!({"data": {"repository": {
+        "issues": size(issues, "2020-01-01T00:00:00Z"),
+        "newestIssue": size(issues, "2020-01-06T00:00:00Z"),
+        "pullRequests": size(prs, "2020-01-01T00:00:00Z"),
+        "newestPullRequest": size(prs, "2020-01-06T00:00:00Z")
+    }}})
+    .to_string()
+}
@@ -1,27 +1,313 @@
 //! Github API types in reduced forms.
 
-use anyhow::Context;
-use curl::easy::{Easy, List};
+use crate::app::{App, Installation};
+use crate::cache::Cache;
+use crate::checkpoint::{Checkpoint, Resumed};
+use crate::cost::{Cost, OverBudget, RateCost};
+use crate::scheduler::Scheduler;
+use crate::sizes::{PageSizes, GROW_AFTER};
+use crate::tokens::Tokens;
+use crate::transport::{Curl, Response, Transport};
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Utc};
+use itertools::Itertools;
 use serde::de::DeserializeOwned;
-use serde::Deserialize;
-use std::io::Read;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-/// The never-changing URL to POST to for any V4 request.
-const V4_URL: &str = "https://api.github.com/graphql";
+/// The URL to POST to for any V4 request against github.com.
+pub const V4_URL: &str = "https://api.github.com/graphql";
 
-#[derive(Debug, Deserialize)]
+/// Where and how to reach the Github API.
+///
+/// For Github Enterprise Server, the GraphQL endpoint is usually
+/// `https://<host>/api/graphql`.
+#[derive(Clone)]
+pub struct Client {
+    url: String,
+    tokens: Arc<Tokens>,
+    /// When set, authenticate as this Github App instead of with `tokens`.
+    app: Option<Arc<App>>,
+    /// Shared by all clones, bounding how many requests are in flight.
+    scheduler: Arc<Scheduler>,
+    /// Where to save the progress of long crawls, and whether to resume them.
+    checkpoints: Option<(PathBuf, bool)>,
+    /// Set once the run has been interrupted. Shared by all clones.
+    stop: Arc<AtomicBool>,
+    /// The page sizes that have worked for each kind of paged query.
+    page_sizes: Arc<PageSizes>,
+    transport: Arc<dyn Transport>,
+    cache: Option<Cache>,
+    /// The most API points the whole run may spend.
+    budget: Option<u32>,
+    /// Points spent by this client and all its clones.
+    total: Arc<Mutex<Cost>>,
+    /// Points spent by this client since it was `scoped`.
+    scope: Option<Arc<Mutex<Cost>>>,
+}
+
+impl Client {
+    /// A client for the GraphQL endpoint at the given URL.
+    pub fn new(url: &str, token: &str) -> Client {
+        Client::pooled(url, vec![token.to_string()])
+    }
+
+    /// A client that rotates between several tokens, moving to the next once
+    /// the current one's quota runs low.
+    pub fn pooled(url: &str, tokens: Vec<String>) -> Client {
+        Client {
+            url: url.trim_end_matches('/').to_string(),
+            tokens: Arc::new(Tokens::new(tokens)),
+            app: None,
+            scheduler: Arc::new(Scheduler::default()),
+            checkpoints: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            page_sizes: Arc::new(PageSizes::new()),
+            transport: Arc::new(Curl::default()),
+            cache: None,
+            budget: None,
+            total: Arc::new(Mutex::new(Cost::default())),
+            scope: None,
+        }
+    }
+
+    /// A client that authenticates as an installation of a Github App. Its
+    /// installation token is renewed as needed.
+    pub fn as_app(url: &str, app: App) -> Client {
+        Client {
+            app: Some(Arc::new(app)),
+            ..Client::pooled(url, vec![])
+        }
+    }
+
+    /// Share out requests according to the given `Scheduler`, instead of the
+    /// default of a few at a time.
+    pub fn with_scheduler(self, scheduler: Scheduler) -> Client {
+        Client {
+            scheduler: Arc::new(scheduler),
+            ..self
+        }
+    }
+
+    /// Save the progress of long crawls under the given directory. If
+    /// `resume` is set, carry on from whatever an earlier run saved there.
+    pub fn with_checkpoints(self, dir: PathBuf, resume: bool) -> Client {
+        Client {
+            checkpoints: Some((dir, resume)),
+            ..self
+        }
+    }
+
+    /// Remember page sizes with the given `PageSizes`, say to keep them
+    /// between runs.
+    pub fn with_page_sizes(self, page_sizes: PageSizes) -> Client {
+        Client {
+            page_sizes: Arc::new(page_sizes),
+            ..self
+        }
+    }
+
+    /// Send requests through something other than the real network, like
+    /// [`Fixtures`](struct.Fixtures.html).
+    pub fn with_transport<T: Transport + 'static>(self, transport: T) -> Client {
+        Client {
+            transport: Arc::new(transport),
+            ..self
+        }
+    }
+
+    /// A client for the public github.com API.
+    pub fn github(token: &str) -> Client {
+        Client::new(V4_URL, token)
+    }
+
+    /// Keep successful responses on disk, and answer repeated requests from
+    /// there.
+    pub fn with_cache(self, cache: Cache) -> Client {
+        Client {
+            cache: Some(cache),
+            ..self
+        }
+    }
+
+    /// The same client, but which always asks Github directly.
+    pub fn without_cache(self) -> Client {
+        Client {
+            cache: None,
+            ..self
+        }
+    }
+
+    /// Stop making requests once they'd push the total cost of the run past
+    /// this many API points. Paged lookups then end early, as if they had
+    /// reached the last page.
+    pub fn with_budget(self, points: u32) -> Client {
+        Client {
+            budget: Some(points),
+            ..self
+        }
+    }
+
+    /// The scheduler that requests of this client wait their turn with.
+    pub(crate) fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    /// A clone of this client which also tallies its own costs separately,
+    /// say for a single repository. Costs still count toward the total.
+    pub fn scoped(&self) -> Client {
+        Client {
+            scope: Some(Arc::new(Mutex::new(Cost::default()))),
+            ..self.clone()
+        }
+    }
+
+    /// The API points spent since this client was `scoped`, or in total if it
+    /// never was.
+    pub fn cost(&self) -> Cost {
+        let ledger = self.scope.as_ref().unwrap_or(&self.total);
+        ledger.lock().map(|c| c.clone()).unwrap_or_default()
+    }
+
+    /// The API points spent by this client and all its clones.
+    pub fn total_cost(&self) -> Cost {
+        self.total.lock().map(|c| c.clone()).unwrap_or_default()
+    }
+
+    /// The token to authenticate the next request with, and its position in
+    /// the pool. App installation tokens are renewed here before they expire.
+    fn credentials(&self) -> anyhow::Result<(usize, String)> {
+        match self.app.as_ref() {
+            None => Ok(self.tokens.current()),
+            Some(app) => {
+                let token = app.token(|jwt| exchange(self, &app.exchange_path(), jwt))?;
+                Ok((0, token))
+            }
+        }
+    }
+
+    /// Stop making requests, say because the user pressed Ctrl-C. Paged
+    /// lookups then end early, as if they had run out of pages. Affects every
+    /// clone of this client.
+    pub fn interrupt(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+
+    /// Has the run been interrupted?
+    pub fn is_interrupted(&self) -> bool {
+        self.stop.load(Ordering::SeqCst)
+    }
+
+    /// Did paging end before running out of pages, be it from an interruption
+    /// or the budget being spent?
+    pub(crate) fn stopped_early(&self) -> bool {
+        self.is_interrupted() || self.total_cost().over_budget
+    }
+
+    /// Sleep, but wake up early if interrupted.
+    fn wait(&self, delay: Duration) -> Result<(), Interrupted> {
+        let until = Instant::now() + delay;
+        while !self.is_interrupted() {
+            match until.checked_duration_since(Instant::now()) {
+                None => return Ok(()),
+                Some(left) => thread::sleep(left.min(Duration::from_millis(100))),
+            }
+        }
+        Err(Interrupted)
+    }
+
+    /// The saved progress of fetching some kind of thread from a repository,
+    /// if checkpoints are enabled.
+    pub(crate) fn checkpoint(
+        &self,
+        owner: &str,
+        repo: &str,
+        kind: &str,
+        end: &Option<DateTime<Utc>>,
+    ) -> anyhow::Result<Option<(Checkpoint, Resumed)>> {
+        match self.checkpoints.as_ref() {
+            None => Ok(None),
+            Some((dir, resume)) => {
+                let path = Checkpoint::path(dir, self.host(), owner, repo, kind, end);
+                Checkpoint::open(path, *resume).map(Some)
+            }
+        }
+    }
+
+    fn ledgers(&self) -> impl Iterator<Item = &Arc<Mutex<Cost>>> {
+        std::iter::once(&self.total).chain(self.scope.as_ref())
+    }
+
+    fn charge(&self, rc: &RateCost) {
+        for ledger in self.ledgers() {
+            if let Ok(mut cost) = ledger.lock() {
+                cost.charge(rc);
+            }
+        }
+    }
+
+    /// Refuse to continue if another request like the most expensive so far
+    /// would exceed the budget.
+    fn check_budget(&self) -> Result<(), OverBudget> {
+        let budget = match self.budget {
+            None => return Ok(()),
+            Some(b) => b,
+        };
+        let total = self.total_cost();
+
+        if total.points + total.largest.max(1) > budget {
+            for ledger in self.ledgers() {
+                if let Ok(mut cost) = ledger.lock() {
+                    cost.over_budget = true;
+                }
+            }
+            Err(OverBudget { budget })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The GraphQL endpoint this client talks to.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The hostname of the endpoint, as used to pick host-specific tokens.
+    pub fn host(&self) -> &str {
+        host(&self.url)
+    }
+
+    /// The full URL of a REST (v3) call against the same host.
+    pub fn rest_url(&self, path: &str) -> String {
+        let base = match self.url.strip_suffix("/graphql") {
+            // Enterprise Server: `/api/graphql` sits beside `/api/v3`.
+            Some(b) if b.ends_with("/api") => format!("{}/v3", b),
+            Some(b) => b.to_string(),
+            None => self.url.clone(),
+        };
+        format!("{}/{}", base, path.trim_start_matches('/'))
+    }
+}
+
+/// The hostname portion of some URL.
+pub fn host(url: &str) -> &str {
+    let rest = url.split("://").nth(1).unwrap_or(url);
+    rest.split(['/', ':']).next().unwrap_or(rest)
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PageInfo {
     pub has_next_page: bool,
     pub end_cursor: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct Edges<A> {
-    pub edges: Vec<Node<A>>,
-}
-
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Node<A> {
     pub node: A,
 }
@@ -29,45 +315,644 @@ pub struct Node<A> {
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Paged<A> {
+    /// Only present if the query asked for it.
+    #[serde(default, alias = "issueCount")]
+    pub total_count: Option<usize>,
     pub page_info: PageInfo,
     pub edges: Vec<Node<A>>,
 }
 
+/// A GraphQL query document along with the values of its variables.
+#[derive(Serialize)]
+pub struct Request {
+    pub query: &'static str,
+    pub variables: serde_json::Value,
+    /// Give up at the first timeout, since a smaller page might do better.
+    #[serde(skip)]
+    shrinkable: bool,
+}
+
+impl Request {
+    pub fn new(query: &'static str, variables: serde_json::Value) -> Request {
+        Request {
+            query,
+            variables,
+            shrinkable: false,
+        }
+    }
+
+    fn shrinkable(self, shrinkable: bool) -> Request {
+        Request { shrinkable, ..self }
+    }
+}
+
 /// The top-level results of a GraphQL query.
 #[derive(Deserialize)]
-struct Query<A> {
-    pub data: A,
+struct Query {
+    data: Option<serde_json::Value>,
+    #[serde(default)]
+    errors: Vec<RawError>,
 }
 
-/// Perform some generalized Github query.
-pub fn lookup<A: DeserializeOwned>(token: &str, query: String) -> anyhow::Result<A> {
-    let mut handle = Easy::new();
-    let mut resp: Vec<u8> = Vec::new();
-    handle.url(V4_URL)?;
-    handle.fail_on_error(true)?;
-    handle.post(true)?;
-    handle.post_field_size(query.len() as u64)?;
-
-    // --- Add Headers --- //
-    let mut headers = List::new();
-    headers.append(&format!("authorization: bearer {}", token))?;
-    headers.append("user-agent: credit")?;
-    handle.http_headers(headers)?;
-
-    // Blocked off to allow `resp` to be borrowed immutably below.
+/// An error as reported in the `errors` field of a GraphQL response.
+#[derive(Deserialize)]
+struct RawError {
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    message: String,
+    #[serde(default)]
+    path: Vec<serde_json::Value>,
+}
+
+/// The broad categories of GraphQL query failures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Something in the query, like a repository, doesn't exist.
+    NotFound,
+    /// The token's quota has been exhausted.
+    RateLimited,
+    /// The token isn't allowed to see something, or lacks the needed scopes.
+    Forbidden,
+    /// Github gave up on the query before it finished.
+    Timeout,
+    /// Anything else, like a malformed query or a bad cursor.
+    Other,
+}
+
+impl ErrorKind {
+    /// Would trying again later plausibly help?
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, ErrorKind::RateLimited | ErrorKind::Timeout)
+    }
+
+    /// A suggestion to the user as to what might have gone wrong.
+    pub fn advice(&self) -> &'static str {
+        match self {
+            ErrorKind::NotFound => "Not found. Check the spelling of the name.",
+            ErrorKind::RateLimited => "Rate limited. Try again later.",
+            ErrorKind::Forbidden => "Forbidden. Does your token have the required scopes?",
+            ErrorKind::Timeout => "Github timed out. Try again later.",
+            ErrorKind::Other => "The query failed.",
+        }
+    }
+}
+
+/// A single error reported by the GraphQL API.
+#[derive(Debug)]
+pub struct QueryError {
+    pub kind: ErrorKind,
+    /// The field of the query responsible for the error, like `repository`.
+    pub path: String,
+    pub message: String,
+}
+
+impl From<RawError> for QueryError {
+    fn from(raw: RawError) -> Self {
+        let kind = match raw.kind.as_deref() {
+            Some("NOT_FOUND") => ErrorKind::NotFound,
+            Some("RATE_LIMITED") => ErrorKind::RateLimited,
+            Some("FORBIDDEN") | Some("INSUFFICIENT_SCOPES") => ErrorKind::Forbidden,
+            _ if raw.message.to_lowercase().contains("timeout") => ErrorKind::Timeout,
+            _ => ErrorKind::Other,
+        };
+        let path = raw
+            .path
+            .iter()
+            .map(|p| match p {
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            })
+            .join(".");
+
+        QueryError {
+            kind,
+            path,
+            message: raw.message,
+        }
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{} (at `{}`)", self.message, self.path)
+        }
+    }
+}
+
+/// All the errors reported by a GraphQL query, along with any data that was
+/// still returned.
+#[derive(Debug)]
+pub struct QueryErrors {
+    pub errors: Vec<QueryError>,
+    /// Whatever `data` came back alongside the errors, if any.
+    pub partial: Option<serde_json::Value>,
+}
+
+impl QueryErrors {
+    /// The category of the first error, which is usually the only one.
+    pub fn kind(&self) -> ErrorKind {
+        self.errors
+            .first()
+            .map(|e| e.kind)
+            .unwrap_or(ErrorKind::Other)
+    }
+
+    /// Attempt to decode whatever partial data came back.
+    pub fn partial<A: DeserializeOwned>(&self) -> Option<A> {
+        self.partial
+            .clone()
+            .and_then(|v| serde_json::from_value(v).ok())
+    }
+}
+
+impl fmt::Display for QueryErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.errors.iter().join("\n"))
+    }
+}
+
+impl std::error::Error for QueryErrors {}
+
+/// Decode the `data` of a successful response, or its `errors` if there were
+/// any. Any `rateLimit` cost that was requested alongside the data is returned
+/// too.
+fn decode<A: DeserializeOwned>(resp: &Response) -> anyhow::Result<(A, Option<RateCost>)> {
+    let query: Query = serde_json::from_slice(&resp.body).with_context(|| {
+        format!(
+            "The response couldn't be decoded into JSON:\n{}",
+            resp.text()
+        )
+    })?;
+
+    match query.data {
+        Some(data) if query.errors.is_empty() => {
+            let cost = data
+                .get("rateLimit")
+                .and_then(|rl| RateCost::deserialize(rl).ok());
+            let a = serde_json::from_value(data).with_context(|| {
+                format!("The response had an unexpected shape:\n{}", resp.text())
+            })?;
+            Ok((a, cost))
+        }
+        None if query.errors.is_empty() => {
+            Err(anyhow!("The response contained no data:\n{}", resp.text()))
+        }
+        data => Err(QueryErrors {
+            errors: query.errors.into_iter().map(QueryError::from).collect(),
+            partial: data.filter(|d| !d.is_null()),
+        }
+        .into()),
+    }
+}
+
+impl Response {
+    /// The API points left in the token's quota.
+    fn remaining(&self) -> Option<u32> {
+        self.header("x-ratelimit-remaining")?.parse().ok()
+    }
+
+    /// When the token's quota resets, in seconds since the epoch.
+    fn reset_at(&self) -> Option<u64> {
+        self.header("x-ratelimit-reset")?.parse().ok()
+    }
+
+    /// Has Github asked us to slow down, via its secondary rate limits?
+    fn is_throttled(&self) -> bool {
+        let secondary = || {
+            let text = self.text().to_lowercase();
+            text.contains("secondary rate limit") || text.contains("abuse")
+        };
+
+        self.header("retry-after").is_some()
+            || self.code == 429
+            || (self.code == 403 && secondary())
+    }
+
+    /// Did GraphQL report the quota as exhausted?
+    fn is_rate_limited(&self) -> bool {
+        self.code == 200 && self.text().contains("RATE_LIMITED")
+    }
+
+    /// How long to wait until the primary rate limit resets, if it's been
+    /// exhausted.
+    fn quota_reset(&self) -> Option<Duration> {
+        if self.remaining()? != 0 {
+            return None;
+        }
+
+        let reset = self.reset_at()?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+        Some(Duration::from_secs(reset.saturating_sub(now) + 1))
+    }
+
+    /// If this response is worth trying again, how long should we wait first?
+    fn retry_delay(&self, attempt: u32) -> Option<Duration> {
+        if let Some(secs) = self.header("retry-after").and_then(|s| s.parse().ok()) {
+            Some(Duration::from_secs(secs))
+        } else if let Some(reset) = self.quota_reset() {
+            Some(reset)
+        } else if self.code >= 500 || self.is_throttled() {
+            Some(backoff(attempt))
+        } else {
+            None
+        }
+    }
+}
+
+/// The error given in place of a request made after
+/// [`Client::interrupt`](struct.Client.html#method.interrupt).
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Interrupted.")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// Github gave up on a request that asked for more than one result, which a
+/// smaller page might avoid.
+#[derive(Debug)]
+pub struct TooHeavy;
+
+impl fmt::Display for TooHeavy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Github timed out on a page of results.")
+    }
+}
+
+impl std::error::Error for TooHeavy {}
+
+/// Fetches the page of the given size at some cursor, or the first page given
+/// `None`.
+type Fetch<'a, A> = Box<dyn FnMut(Option<&str>, u32) -> anyhow::Result<Paged<A>> + Send + 'a>;
+
+/// Successive pages of some paginated query. Each page is only fetched once
+/// the previous one has been consumed, so callers can process results as they
+/// arrive and stop whenever they like. Paging ends quietly once the client's
+/// budget is spent, or after the first error.
+///
+/// Should Github time out on a page, it's asked for again at half the size.
+/// After a run of successes the size grows back, and whichever size was last
+/// used is remembered by the client for the next crawl of the same kind.
+pub struct Pages<'a, A> {
+    fetch: Fetch<'a, A>,
+    cursor: Option<String>,
+    done: bool,
+    sizes: Arc<PageSizes>,
+    /// What the size is remembered under.
+    key: String,
+    size: u32,
+    max: u32,
+    /// Pages fetched in a row at the current size.
+    streak: u32,
+}
+
+impl<'a, A: 'a> Pages<'a, A> {
+    /// Pages of the given `query`, which is given the cursor of the page to
+    /// fetch (`None` for the first) and the page size. Each response is turned
+    /// into a page by `extract`.
+    ///
+    /// Unless a size is remembered under `key`, pages start at `start`
+    /// results, and never grow past `max`.
+    pub(crate) fn new<R, Q, E>(
+        client: &'a Client,
+        key: &str,
+        (start, max): (u32, u32),
+        mut query: Q,
+        extract: E,
+    ) -> Pages<'a, A>
+    where
+        R: DeserializeOwned,
+        Q: FnMut(Option<&str>, u32) -> Request + Send + 'a,
+        E: Fn(R) -> Paged<A> + Send + 'a,
     {
-        let mut tx = handle.transfer();
-        tx.read_function(move |buf| Ok(query.as_bytes().read(buf).unwrap_or(0)))?;
-        tx.write_function(|data| {
-            resp.extend_from_slice(data);
-            Ok(data.len())
-        })?;
-        tx.perform()?;
+        let key = format!("{}/{}", client.host(), key);
+        let size = client.page_sizes.get(&key).unwrap_or(start).clamp(1, max);
+        let fetch = move |cursor: Option<&str>, size: u32| {
+            let request = query(cursor, size).shrinkable(size > 1);
+            lookup(client, &request).map(&extract)
+        };
+
+        Pages {
+            fetch: Box::new(fetch),
+            cursor: None,
+            done: false,
+            sizes: client.page_sizes.clone(),
+            key,
+            size,
+            max,
+            streak: 0,
+        }
+    }
+
+    /// Carry on from the page at the given cursor, rather than the first.
+    pub fn resume(self, cursor: String) -> Pages<'a, A> {
+        Pages {
+            cursor: Some(cursor),
+            ..self
+        }
+    }
+
+    /// Every result of every page, in order.
+    pub fn items(self) -> impl Iterator<Item = anyhow::Result<A>> + 'a {
+        self.map_ok(|page| page.edges.into_iter().map(|n| n.node))
+            .flatten_ok()
+    }
+}
+
+impl<A> Iterator for Pages<'_, A> {
+    type Item = anyhow::Result<Paged<A>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let fetched = loop {
+            match (self.fetch)(self.cursor.as_deref(), self.size) {
+                Err(e) if e.is::<TooHeavy>() => {
+                    self.size = (self.size / 2).max(1);
+                    self.streak = 0;
+                    self.sizes.set(&self.key, self.size);
+                }
+                fetched => break fetched,
+            }
+        };
+
+        match fetched {
+            // The budget is spent or the run interrupted, so stop as if the
+            // last page had been reached.
+            Err(e) if e.is::<OverBudget>() || e.is::<Interrupted>() => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+            Ok(page) => {
+                self.streak += 1;
+                if self.streak >= GROW_AFTER && self.size < self.max {
+                    self.size = (self.size * 2).min(self.max);
+                    self.streak = 0;
+                    self.sizes.set(&self.key, self.size);
+                }
+
+                match page.page_info.end_cursor.as_ref() {
+                    Some(c) if page.page_info.has_next_page => self.cursor = Some(c.clone()),
+                    _ => self.done = true,
+                }
+                Some(Ok(page))
+            }
+        }
+    }
+}
+
+/// Only attempt a single request this many times.
+const MAX_ATTEMPTS: u32 = 8;
+
+/// The wait before the first retry, doubled for every attempt thereafter.
+const BASE_DELAY: Duration = Duration::from_secs(2);
+
+/// The longest we'll wait between attempts, unless Github tells us otherwise.
+const MAX_DELAY: Duration = Duration::from_secs(300);
+
+fn backoff(attempt: u32) -> Duration {
+    BASE_DELAY
+        .checked_mul(2u32.saturating_pow(attempt - 1))
+        .map(|d| d.min(MAX_DELAY))
+        .unwrap_or(MAX_DELAY)
+}
+
+/// Did the request take too long?
+fn timed_out(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<curl::Error>()
+        .map(|e| e.is_operation_timedout())
+        .unwrap_or(false)
+}
+
+/// Is this a network hiccup that might go away by itself?
+fn transient(e: &anyhow::Error) -> bool {
+    let e = match e.downcast_ref::<curl::Error>() {
+        Some(e) => e,
+        None => return false,
+    };
+
+    e.is_operation_timedout()
+        || e.is_couldnt_connect()
+        || e.is_couldnt_resolve_host()
+        || e.is_send_error()
+        || e.is_recv_error()
+        || e.is_got_nothing()
+        || e.is_partial_file()
+        || e.is_ssl_connect_error()
+}
+
+/// Perform some generalized Github query.
+///
+/// Fails with [`OverBudget`](struct.OverBudget.html) if the client's budget
+/// has been reached.
+///
+/// Server errors, network hiccups and rate limiting are retried with
+/// exponential backoff. If the primary rate limit has been exhausted, this
+/// sleeps until it resets.
+pub fn lookup<A: DeserializeOwned>(client: &Client, request: &Request) -> anyhow::Result<A> {
+    let query = serde_json::to_string(request)?;
+    let cached = client
+        .cache
+        .as_ref()
+        .and_then(|c| c.get(&client.url, &query))
+        .and_then(|body| decode(&Response::ok(body)).ok());
+
+    if let Some((a, _)) = cached {
+        return Ok(a);
     }
 
-    let text = std::str::from_utf8(&resp)?;
-    let result: Query<A> = serde_json::from_str(&text)
-        .with_context(|| format!("The response couldn't be decoded into JSON:\n{}", text))?;
+    if client.is_interrupted() {
+        return Err(Interrupted.into());
+    }
+
+    client.check_budget()?;
+
+    let mut attempt = 1;
+
+    loop {
+        let (ix, token) = client.credentials()?;
+        let sent = {
+            let _turn = client.scheduler.acquire();
+            send(client, &token, &query)
+        };
+        let resp = match sent {
+            Err(e) if request.shrinkable && timed_out(&e) => return Err(TooHeavy.into()),
+            Err(e) if attempt < MAX_ATTEMPTS && transient(&e) => {
+                client.wait(backoff(attempt))?;
+                attempt += 1;
+                continue;
+            }
+            Err(e) => return Err(e),
+            Ok(resp) => resp,
+        };
+
+        if let Some(remaining) = resp.remaining() {
+            client.tokens.observe(ix, remaining, resp.reset_at());
+        }
+
+        let delay = if resp.code == 200 {
+            match decode(&resp) {
+                Ok((a, cost)) => {
+                    if let Some(cost) = cost {
+                        client.charge(&cost);
+                    }
+                    if let Some(cache) = client.cache.as_ref() {
+                        // A failure to cache shouldn't fail the query itself.
+                        let _ = cache.put(&client.url, &query, &resp.body);
+                    }
+                    return Ok(a);
+                }
+                Err(e) => {
+                    let delay = match e.downcast_ref::<QueryErrors>().map(|qe| qe.kind()) {
+                        Some(ErrorKind::RateLimited) => {
+                            Some(resp.quota_reset().unwrap_or_else(|| backoff(attempt)))
+                        }
+                        Some(ErrorKind::Timeout) if request.shrinkable => {
+                            return Err(TooHeavy.into())
+                        }
+                        Some(ErrorKind::Timeout) => Some(backoff(attempt)),
+                        _ => None,
+                    };
+
+                    match delay {
+                        Some(d) if attempt < MAX_ATTEMPTS => d,
+                        _ => return Err(e),
+                    }
+                }
+            }
+        } else if resp.code == 401 && attempt < MAX_ATTEMPTS && client.app.is_some() {
+            // The installation token may have been revoked, so mint another.
+            if let Some(app) = client.app.as_ref() {
+                app.invalidate();
+            }
+            attempt += 1;
+            continue;
+        } else if request.shrinkable && (resp.code == 502 || resp.code == 504) {
+            return Err(TooHeavy.into());
+        } else {
+            match resp.retry_delay(attempt) {
+                Some(d) if attempt < MAX_ATTEMPTS => d,
+                _ => {
+                    return Err(anyhow!(
+                        "Github responded with HTTP {}:\n{}",
+                        resp.code,
+                        resp.text()
+                    ))
+                }
+            }
+        };
+
+        // If this token's quota has run out, there may be another one to
+        // carry on with right away.
+        let exhausted = resp.quota_reset().is_some() || resp.is_rate_limited();
+        if exhausted && client.tokens.exhausted(ix, resp.reset_at()) {
+            continue;
+        }
+
+        // Every other request would only be throttled as well.
+        if resp.is_throttled() {
+            client.scheduler.pause(delay);
+        }
+
+        if delay >= MAX_DELAY {
+            eprintln!(
+                "Github rate limit reached. Waiting {} minutes before continuing...",
+                delay.as_secs() / 60
+            );
+        }
+
+        client.wait(delay)?;
+        attempt += 1;
+    }
+}
+
+/// Trade a Github App's JWT for an installation token.
+fn exchange(client: &Client, path: &str, jwt: &str) -> anyhow::Result<Installation> {
+    let headers = [
+        format!("authorization: Bearer {}", jwt),
+        "accept: application/vnd.github+json".to_string(),
+        "user-agent: credit".to_string(),
+    ];
+    let resp = client
+        .transport
+        .post(&client.rest_url(path), &headers, &[])?;
+
+    if resp.code != 201 {
+        return Err(anyhow!(
+            "Github App authentication failed with HTTP {}:\n{}",
+            resp.code,
+            resp.text()
+        ));
+    }
+
+    serde_json::from_slice(&resp.body).context("Unexpected installation token response")
+}
+
+/// POST a query to Github once.
+fn send(client: &Client, token: &str, query: &str) -> anyhow::Result<Response> {
+    let headers = [
+        format!("authorization: bearer {}", token),
+        "user-agent: credit".to_string(),
+    ];
+    client
+        .transport
+        .post(&client.url, &headers, query.as_bytes())
+}
+
+#[test]
+fn enterprise_urls() {
+    let dotcom = Client::github("abc");
+    assert_eq!("api.github.com", dotcom.host());
+    assert_eq!(
+        "https://api.github.com/app/installations",
+        dotcom.rest_url("/app/installations")
+    );
+
+    let ghes = Client::new("https://git.example.com:8443/api/graphql/", "abc");
+    assert_eq!("git.example.com", ghes.host());
+    assert_eq!(
+        "https://git.example.com:8443/api/v3/app/installations",
+        ghes.rest_url("app/installations")
+    );
+}
+
+#[test]
+fn backoff_growth() {
+    assert_eq!(Duration::from_secs(2), backoff(1));
+    assert_eq!(Duration::from_secs(16), backoff(4));
+    assert_eq!(MAX_DELAY, backoff(30));
+}
+
+#[test]
+fn graphql_errors() {
+    let resp = Response::ok(
+        br#"{
+          "data": { "repository": null },
+          "errors": [{
+            "type": "NOT_FOUND",
+            "path": ["repository"],
+            "message": "Could not resolve to a Repository with the name 'fosskers/nope'."
+          }]
+        }"#
+        .to_vec(),
+    );
 
-    Ok(result.data)
+    let err = decode::<serde_json::Value>(&resp).map(|_| ()).unwrap_err();
+    let qe = err.downcast_ref::<QueryErrors>().unwrap();
+    assert_eq!(ErrorKind::NotFound, qe.kind());
+    assert_eq!("repository", qe.errors[0].path);
+    assert!(qe.partial.is_some());
 }
@@ -1,21 +1,47 @@
 //! A library for measuring Github repository contributions.
 
-mod contribs;
+mod app;
+mod cache;
+mod checkpoint;
+pub mod contribs;
+mod cost;
+mod estimate;
+#[cfg(test)]
+mod fixtures;
 mod github;
 mod limit;
-mod repo;
+pub mod repo;
+mod scheduler;
+mod sizes;
+mod store;
+mod tokens;
+mod transport;
 
 // Re-export.
-pub use limit::rate_limit;
-
+pub use app::App;
+pub use cache::{Cache, CacheStats, DEFAULT_TTL};
+pub use cost::{Cost, OverBudget};
+pub use estimate::{estimate, Estimate, RepoEstimate};
+pub use github::{
+    host, Client, ErrorKind, Interrupted, Node, PageInfo, Paged, Pages, QueryError, QueryErrors,
+    V4_URL,
+};
+pub use limit::{rate_limit, RateLimit};
+pub use scheduler::{Scheduler, DEFAULT_CONCURRENCY};
+pub use sizes::PageSizes;
+pub use transport::{Curl, Fixtures, Network, Recorder, Replay, Response, Stats, Transport};
+
+use anyhow::{anyhow, Context};
 use chrono::{DateTime, Utc};
 use counter::Counter;
-use indicatif::ProgressBar;
+use indicatif::{ProgressBar, ProgressStyle};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
+use std::path::Path;
 use std::time::Duration;
+use store::Store;
 
 /// A nicer collated form of the data pulled from Github regarding User
 /// Contributions.
@@ -91,6 +117,12 @@ pub struct PR {
     pub thread: Thread,
     pub commits: usize,
     pub merged: Option<DateTime<Utc>>,
+    /// Review counts of everyone but the author who reviewed.
+    pub reviewers: HashMap<String, usize>,
+    /// When, if ever, was the first review by someone other than the author?
+    pub first_review: Option<DateTime<Utc>>,
+    /// When, if ever, was the first approval?
+    pub first_approval: Option<DateTime<Utc>>,
 }
 
 impl PR {
@@ -111,17 +143,29 @@ impl Threaded for PR {
     }
 }
 
+impl Threaded for Thread {
+    fn the_thread(&self) -> &Thread {
+        self
+    }
+}
+
 /// A thread of conversation on Github.
 ///
 /// This could either be associated with an Issue or a PR.
 #[derive(Debug)]
 pub struct Thread {
+    /// What is the thread called?
+    pub title: String,
+    /// Where can it be seen on Github?
+    pub url: String,
     /// Who opened the thread?
     pub author: String,
     /// When was the thread opened?
     pub posted: DateTime<Utc>,
     /// If it's already closed, when was it?
     pub closed: Option<DateTime<Utc>>,
+    /// Why is an Issue closed (or open again)? Never known for PRs.
+    pub state_reason: Option<repo::StateReason>,
     /// Who responded first?
     pub first_responder: Option<String>,
     /// When, if ever, was the first response?
@@ -130,6 +174,23 @@ pub struct Thread {
     pub first_official_response: Option<DateTime<Utc>>,
     /// Comment counts of everyone who participated.
     pub comments: HashMap<String, usize>,
+    /// The names of the labels the thread has.
+    pub labels: Vec<String>,
+    /// How many 👍 reactions does the thread have?
+    pub upvotes: usize,
+    /// Reaction counts of everyone who reacted to the thread.
+    pub reactors: HashMap<String, usize>,
+    /// Were there too many comments or reviews to fetch along with the thread
+    /// itself?
+    pub followed_up: bool,
+}
+
+impl Thread {
+    /// Does the thread have the given label? Like on Github, case doesn't
+    /// matter.
+    pub fn has_label(&self, label: &str) -> bool {
+        self.labels.iter().any(|l| l.eq_ignore_ascii_case(label))
+    }
 }
 
 /// A collection of Issue and Pull Request [`Thread`](struct.Thread.html)s.
@@ -151,12 +212,71 @@ impl Postings {
         Postings { issues, prs }
     }
 
+    /// Keep only the threads with at least one of the `wanted` labels (if any
+    /// are given), and none of the `unwanted` ones.
+    pub fn with_labels(self, wanted: &[String], unwanted: &[String]) -> Postings {
+        let keep = |t: &Thread| {
+            (wanted.is_empty() || wanted.iter().any(|l| t.has_label(l)))
+                && !unwanted.iter().any(|l| t.has_label(l))
+        };
+
+        Postings {
+            issues: self.issues.into_iter().filter(|i| keep(&i.0)).collect(),
+            prs: self.prs.into_iter().filter(|p| keep(&p.thread)).collect(),
+        }
+    }
+
+    /// Statistics for the Issues and PRs of each label. As with filtering,
+    /// case doesn't matter, so labels are grouped under their lowercase
+    /// spelling.
+    pub fn label_statistics(&self) -> BTreeMap<String, LabelStatistics> {
+        let mut by_label: BTreeMap<String, Vec<&Thread>> = BTreeMap::new();
+        self.issues
+            .iter()
+            .map(|i| &i.0)
+            .chain(self.prs.iter().map(|p| &p.thread))
+            .for_each(|t| {
+                for label in t.labels.iter().map(|l| l.to_lowercase()).unique() {
+                    by_label.entry(label).or_default().push(t);
+                }
+            });
+
+        by_label
+            .into_iter()
+            .map(|(label, threads)| {
+                let stats = LabelStatistics {
+                    threads: threads.len(),
+                    closed: threads.iter().filter(|t| t.closed.is_some()).count(),
+                    first_resp_time: self
+                        .resp_times(|| threads.iter().copied(), |t| t.first_response),
+                    close_time: self.resp_times(|| threads.iter().copied(), |t| t.closed),
+                };
+                (label, stats)
+            })
+            .collect()
+    }
+
     /// Consumes the `Postings` to form all the statistics.
     pub fn statistics(self) -> Statistics {
         let all_issues = self.issues.len();
 
         let all_closed_issues = self.issues.iter().filter(|i| i.0.closed.is_some()).count();
 
+        let closed_as = |reason: repo::StateReason| {
+            let closed = || {
+                self.issues
+                    .iter()
+                    .filter(move |i| i.0.closed.is_some() && i.0.state_reason == Some(reason))
+            };
+            (closed().count(), self.resp_times(closed, |i| i.0.closed))
+        };
+
+        let (issues_completed, issue_completed_time) = closed_as(repo::StateReason::Completed);
+
+        let (issues_not_planned, issue_not_planned_time) = closed_as(repo::StateReason::NotPlanned);
+
+        let (issues_duplicate, issue_duplicate_time) = closed_as(repo::StateReason::Duplicate);
+
         let issues_with_responses = self
             .issues
             .iter()
@@ -200,6 +320,42 @@ impl Postings {
 
         let pr_merge_time = self.resp_times(|| self.prs.iter(), |p| p.merged);
 
+        let pr_first_review_time = self.resp_times(|| self.prs.iter(), |p| p.first_review);
+
+        let pr_first_approval_time = self.resp_times(|| self.prs.iter(), |p| p.first_approval);
+
+        let reviewers = self
+            .prs
+            .iter()
+            .map(|p| p.reviewers.clone())
+            .fold(HashMap::new(), hashmap_combine);
+
+        let most_upvoted = self
+            .issues
+            .iter()
+            .filter(|i| i.0.closed.is_none() && i.0.upvotes > 0)
+            .sorted_by(|a, b| b.0.upvotes.cmp(&a.0.upvotes))
+            .map(|i| Upvoted {
+                title: i.0.title.clone(),
+                url: i.0.url.clone(),
+                upvotes: i.0.upvotes,
+            })
+            .collect();
+
+        let upvotes_vs_resp_time = upvote_correlation(&self.issues, |t| t.first_response);
+
+        let upvotes_vs_close_time = upvote_correlation(&self.issues, |t| t.closed);
+
+        let reactors = self
+            .issues
+            .iter()
+            .map(|i| i.0.reactors.clone())
+            .chain(self.prs.iter().map(|p| p.thread.reactors.clone()))
+            .fold(HashMap::new(), hashmap_combine);
+
+        let threads_followed_up = self.issues.iter().filter(|i| i.0.followed_up).count()
+            + self.prs.iter().filter(|p| p.thread.followed_up).count();
+
         let mut contributor_commits = HashMap::new();
         self.prs
             .iter()
@@ -236,6 +392,12 @@ impl Postings {
             contributor_commits,
             all_issues,
             all_closed_issues,
+            issues_completed,
+            issues_not_planned,
+            issues_duplicate,
+            issue_completed_time,
+            issue_not_planned_time,
+            issue_duplicate_time,
             issues_with_responses,
             issues_with_official_responses,
             issue_first_resp_time,
@@ -248,6 +410,16 @@ impl Postings {
             pr_first_resp_time,
             pr_official_first_resp_time,
             pr_merge_time,
+            pr_first_review_time,
+            pr_first_approval_time,
+            reviewers,
+            reactors,
+            most_upvoted,
+            upvotes_vs_resp_time,
+            upvotes_vs_close_time,
+            threads_followed_up,
+            labels: BTreeMap::new(),
+            meta: Meta::default(),
         }
     }
 
@@ -324,6 +496,12 @@ pub struct Statistics {
     pub commentors: HashMap<String, usize>,
     /// All users who had PRs merged.
     pub code_contributors: HashMap<String, usize>,
+    /// The count of PR reviews given by each user.
+    #[serde(default)]
+    pub reviewers: HashMap<String, usize>,
+    /// The count of reactions to Issues and PRs left by each user.
+    #[serde(default)]
+    pub reactors: HashMap<String, usize>,
     /// The commits-in-merged-PRs count for each user.
     #[serde(default)]
     pub contributor_commits: HashMap<String, usize>,
@@ -331,6 +509,24 @@ pub struct Statistics {
     pub all_issues: usize,
     /// How many of the issues have been closed?
     pub all_closed_issues: usize,
+    /// How many issues were closed as completed?
+    #[serde(default)]
+    pub issues_completed: usize,
+    /// How many issues were closed as not planned (i.e. "won't fix")?
+    #[serde(default)]
+    pub issues_not_planned: usize,
+    /// How many issues were closed as duplicates of others?
+    #[serde(default)]
+    pub issues_duplicate: usize,
+    /// How long does it take for an issue to be closed as completed?
+    #[serde(default)]
+    pub issue_completed_time: Option<ResponseTimes>,
+    /// How long does it take for an issue to be closed as not planned?
+    #[serde(default)]
+    pub issue_not_planned_time: Option<ResponseTimes>,
+    /// How long does it take for an issue to be closed as a duplicate?
+    #[serde(default)]
+    pub issue_duplicate_time: Option<ResponseTimes>,
     /// All issues that have been responded to in some way.
     pub issues_with_responses: usize,
     /// All issues that have been responded to "officially".
@@ -355,6 +551,78 @@ pub struct Statistics {
     pub prs_closed_without_merging: usize,
     /// How long does it take for PRs to be merged?
     pub pr_merge_time: Option<ResponseTimes>,
+    /// How long does it take for a PR to be reviewed?
+    #[serde(default)]
+    pub pr_first_review_time: Option<ResponseTimes>,
+    /// How long does it take for a PR to be approved?
+    #[serde(default)]
+    pub pr_first_approval_time: Option<ResponseTimes>,
+    /// Open issues with 👍 reactions, the most upvoted first.
+    #[serde(default)]
+    pub most_upvoted: Vec<Upvoted>,
+    /// How closely do the upvotes of issues and how long they take to be
+    /// responded to follow each other? From -1 to 1.
+    #[serde(default)]
+    pub upvotes_vs_resp_time: Option<f64>,
+    /// How closely do the upvotes of issues and how long they take to be
+    /// closed follow each other? From -1 to 1.
+    #[serde(default)]
+    pub upvotes_vs_close_time: Option<f64>,
+    /// How many threads had too many comments or reviews to fetch in one go,
+    /// and so needed follow-up queries?
+    #[serde(default)]
+    pub threads_followed_up: usize,
+    /// Statistics for each label, if they were asked for.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, LabelStatistics>,
+    /// Details about how these statistics were gathered.
+    #[serde(default)]
+    pub meta: Meta,
+}
+
+/// An open Issue that users have asked for.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Upvoted {
+    pub title: String,
+    pub url: String,
+    /// How many 👍 reactions does it have?
+    pub upvotes: usize,
+}
+
+/// Statistics of the Issues and PRs with a particular label.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LabelStatistics {
+    /// How many threads have the label?
+    pub threads: usize,
+    /// How many of them have been closed?
+    pub closed: usize,
+    /// How long does it take for someone to respond to them?
+    pub first_resp_time: Option<ResponseTimes>,
+    /// How long does it take for them to be closed?
+    pub close_time: Option<ResponseTimes>,
+}
+
+/// Details about how a set of [`Statistics`](struct.Statistics.html) were
+/// gathered.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Meta {
+    /// The API points spent on each repository.
+    pub costs: BTreeMap<String, Cost>,
+    /// The API points spent in total.
+    pub total_cost: Option<Cost>,
+    /// Was fetching interrupted, say by Ctrl-C?
+    #[serde(default)]
+    pub interrupted: bool,
+}
+
+impl Meta {
+    /// Was fetching stopped early to stay within a budget?
+    pub fn over_budget(&self) -> bool {
+        self.total_cost
+            .as_ref()
+            .map(|c| c.over_budget)
+            .unwrap_or(false)
+    }
 }
 
 impl Statistics {
@@ -372,19 +640,67 @@ impl Statistics {
                 .map(|rt| (rt.median_time(), rt.average_time()))
                 .unwrap_or_else(|| ("None".to_string(), "None".to_string()));
 
+            // Github doesn't know why some issues were closed, so these
+            // needn't add up.
+            let closed_as = if self.all_closed_issues == 0 {
+                String::new()
+            } else {
+                format!(
+                    r#"
+Of the closed issues:
+- {} ({:.1}%) were completed.
+- {} ({:.1}%) were not planned.
+- {} ({:.1}%) were duplicates.
+"#,
+                    self.issues_completed,
+                    percent(self.issues_completed, self.all_closed_issues),
+                    self.issues_not_planned,
+                    percent(self.issues_not_planned, self.all_closed_issues),
+                    self.issues_duplicate,
+                    percent(self.issues_duplicate, self.all_closed_issues),
+                )
+            };
+
+            let (completed_median, completed_mean) = self
+                .issue_completed_time
+                .map(|rt| (rt.median_time(), rt.average_time()))
+                .unwrap_or_else(|| ("None".to_string(), "None".to_string()));
+
+            let (not_planned_median, not_planned_mean) = self
+                .issue_not_planned_time
+                .map(|rt| (rt.median_time(), rt.average_time()))
+                .unwrap_or_else(|| ("None".to_string(), "None".to_string()));
+
+            let (duplicate_median, duplicate_mean) = self
+                .issue_duplicate_time
+                .map(|rt| (rt.median_time(), rt.average_time()))
+                .unwrap_or_else(|| ("None".to_string(), "None".to_string()));
+
             format!(
                 r#"
 {} issues found, {} of which are now closed ({:.1}%).
 
 - {} ({:.1}%) of these received a response.
 - {} ({:.1}%) have an official response from a repo Owner or organization Member.
-
+{}
 Response Times (any):
 - Median: {}
 - Average: {}
 
 Response Times (official):
 - Median: {}
+- Average: {}
+
+Time-to-Close (completed):
+- Median: {}
+- Average: {}
+
+Time-to-Close (not planned):
+- Median: {}
+- Average: {}
+
+Time-to-Close (duplicate):
+- Median: {}
 - Average: {}"#,
                 self.all_issues,
                 self.all_closed_issues,
@@ -393,10 +709,17 @@ Response Times (official):
                 percent(self.issues_with_responses, self.all_issues),
                 self.issues_with_official_responses,
                 percent(self.issues_with_official_responses, self.all_issues),
+                closed_as,
                 any_median,
                 any_mean,
                 official_median,
                 official_mean,
+                completed_median,
+                completed_mean,
+                not_planned_median,
+                not_planned_mean,
+                duplicate_median,
+                duplicate_mean,
             )
         };
 
@@ -418,6 +741,16 @@ Response Times (official):
                 .map(|rt| (rt.median_time(), rt.average_time()))
                 .unwrap_or_else(|| ("None".to_string(), "None".to_string()));
 
+            let (review_median, review_mean) = self
+                .pr_first_review_time
+                .map(|rt| (rt.median_time(), rt.average_time()))
+                .unwrap_or_else(|| ("None".to_string(), "None".to_string()));
+
+            let (approval_median, approval_mean) = self
+                .pr_first_approval_time
+                .map(|rt| (rt.median_time(), rt.average_time()))
+                .unwrap_or_else(|| ("None".to_string(), "None".to_string()));
+
             format!(
                 r#"
 {} Pull Requests found, {} of which are now merged ({:.1}%).
@@ -434,6 +767,14 @@ Response Times (official):
 - Median: {}
 - Average: {}
 
+Time-to-First-Review:
+- Median: {}
+- Average: {}
+
+Time-to-First-Approval:
+- Median: {}
+- Average: {}
+
 Time-to-Merge:
 - Median: {}
 - Average: {}"#,
@@ -450,18 +791,67 @@ Time-to-Merge:
                 any_mean,
                 official_median,
                 official_mean,
+                review_median,
+                review_mean,
+                approval_median,
+                approval_mean,
                 merge_median,
                 merge_mean,
             )
         };
 
+        let reactions = {
+            let upvoted = if self.most_upvoted.is_empty() {
+                "No open issues have been upvoted.".to_string()
+            } else {
+                self.most_upvoted
+                    .iter()
+                    .take(limit)
+                    .enumerate()
+                    .map(|(i, u)| format!("{:2}. [{}]({}): {}", i + 1, u.title, u.url, u.upvotes))
+                    .join("\n")
+            };
+            let correlation = |c: Option<f64>| {
+                c.map(|c| format!("{:.2}", c))
+                    .unwrap_or_else(|| "None".to_string())
+            };
+
+            format!(
+                r#"
+Top 10 Most-Upvoted Open Issues (by 👍):
+{}
+
+Correlation of 👍 with Response Time: {}
+Correlation of 👍 with Time-to-Close: {}
+
+(From -1 to 1. Below 0, more-upvoted issues are dealt with sooner.)"#,
+                upvoted,
+                correlation(self.upvotes_vs_resp_time),
+                correlation(self.upvotes_vs_close_time),
+            )
+        };
+
+        let followed_up = match self.threads_followed_up {
+            0 => String::new(),
+            n => format!(
+                "\n{} threads had over 100 comments or reviews, which were fetched with follow-up queries.\n",
+                n
+            ),
+        };
+
         let contributors = format!(
             r#"
 Top 10 Commentors (Issues and PRs):
 {}
+{}
+Top 10 Reactors (to Issues and PRs):
+{}
 
 Top 10 Code Contributors (by merged PRs):
 {}
+
+Top 10 Reviewers:
+{}
 "#,
             self.commentors
                 .into_iter()
@@ -470,6 +860,14 @@ Top 10 Code Contributors (by merged PRs):
                 .enumerate()
                 .map(|(i, (name, issues))| format!("{:2}. {}: {}", i + 1, name, issues))
                 .join("\n"),
+            followed_up,
+            self.reactors
+                .into_iter()
+                .sorted_by(|a, b| b.1.cmp(&a.1))
+                .take(limit)
+                .enumerate()
+                .map(|(i, (name, reactions))| format!("{:2}. {}: {}", i + 1, name, reactions))
+                .join("\n"),
             self.code_contributors
                 .into_iter()
                 .sorted_by(|a, b| b.1.cmp(&a.1))
@@ -477,8 +875,48 @@ Top 10 Code Contributors (by merged PRs):
                 .enumerate()
                 .map(|(i, (name, prs))| format!("{:2}. {}: {}", i + 1, name, prs))
                 .join("\n"),
+            self.reviewers
+                .into_iter()
+                .sorted_by(|a, b| b.1.cmp(&a.1))
+                .take(limit)
+                .enumerate()
+                .map(|(i, (name, reviews))| format!("{:2}. {}: {}", i + 1, name, reviews))
+                .join("\n"),
         );
 
+        let labels = if self.labels.is_empty() {
+            String::new()
+        } else {
+            let time = |rt: &Option<ResponseTimes>| {
+                rt.as_ref()
+                    .map(|rt| format!("{} | {}", rt.median_time(), rt.average_time()))
+                    .unwrap_or_else(|| "None | None".to_string())
+            };
+
+            format!(
+                r#"
+## Labels
+
+| Label | Threads | Closed | Response (median) | Response (average) | Close (median) | Close (average) |
+| --- | --- | --- | --- | --- | --- | --- |
+{}
+"#,
+                self.labels
+                    .iter()
+                    .sorted_by(|a, b| b.1.threads.cmp(&a.1.threads))
+                    .map(|(label, ls)| format!(
+                        "| {} | {} | {} ({:.1}%) | {} | {} |",
+                        label,
+                        ls.threads,
+                        ls.closed,
+                        percent(ls.closed, ls.threads),
+                        time(&ls.first_resp_time),
+                        time(&ls.close_time)
+                    ))
+                    .join("\n"),
+            )
+        };
+
         let contributor_commits = if commits {
             format!(
                 r#"
@@ -497,29 +935,40 @@ Top 10 Code Contributors (by commits-in-merged-PRs):
             "".to_string()
         };
 
+        let notes = if self.meta.interrupted {
+            "\n> **Note:** Fetching was interrupted, so this report is partial.\n"
+        } else if self.meta.over_budget() {
+            "\n> **Note:** Fetching stopped early to stay within the API cost budget, so\n> these results are incomplete.\n"
+        } else {
+            ""
+        };
+
         format!(
             r#"# Project Report for {}
-
+{}
 ## Issues
 {}
 
 ## Pull Requests
 {}
 
+## Reactions
+{}
+
 ## Contributors
-{}{}"#,
-            repo, issues, prs, contributors, contributor_commits
+{}{}{}"#,
+            repo, notes, issues, prs, reactions, contributors, contributor_commits, labels
         )
     }
 }
 
 /// Given a repository name, look up the [`Thread`](struct.Thread.html)
 /// statistics of all its Issues.
+#[allow(clippy::too_many_arguments)]
 pub fn repo_threads(
-    token: &str,
+    client: &Client,
     ipb: &ProgressBar,
     ppb: &ProgressBar,
-    serial: bool,
     commits: bool,
     start: &Option<DateTime<Utc>>,
     end: &Option<DateTime<Utc>>,
@@ -529,61 +978,51 @@ pub fn repo_threads(
     let i_msg = format!("Fetching Issues for {}/{}...", owner, repo);
     let p_msg = format!("Fetching Pull Requests for {}/{}...", owner, repo);
 
-    let get_issues = || all_issues(token, start, end, owner, repo);
-    let get_prs = || all_prs(token, start, end, commits, owner, repo);
+    let get_issues = |pb: &ProgressBar| all_issues(client, pb, start, end, owner, repo);
+    let get_prs = |pb: &ProgressBar| all_prs(client, pb, start, end, commits, owner, repo);
 
-    // Too much parallelism can trigger Github's abuse detection, so we offer
-    // the "serial" option here.
-    let (issues, prs) = if serial {
-        let issues = with_progress(ipb, &i_msg, get_issues);
-        let prs = with_progress(ppb, &p_msg, get_prs);
-        (issues, prs)
-    } else {
-        rayon::join(
-            || with_progress(ipb, &i_msg, get_issues),
-            || with_progress(ppb, &p_msg, get_prs),
-        )
-    };
+    // Too much parallelism can trigger Github's abuse detection, but the
+    // client's `Scheduler` keeps the number of requests in flight in check.
+    let (issues, prs) = rayon::join(
+        || with_progress(ipb, &i_msg, get_issues),
+        || with_progress(ppb, &p_msg, get_prs),
+    );
 
     Ok(Postings {
-        issues: issues?,
-        prs: prs?,
+        issues: issues.with_context(|| format!("{}/{} (Issues)", owner, repo))?,
+        prs: prs.with_context(|| format!("{}/{} (Pull Requests)", owner, repo))?,
     })
 }
 
-/// Perform some action with an associated `ProgressBar`.
+/// Perform some action with an associated `ProgressBar`, which it may update
+/// as it goes.
 fn with_progress<F, A>(progress: &ProgressBar, msg: &str, f: F) -> A
 where
-    F: FnOnce() -> A,
+    F: FnOnce(&ProgressBar) -> A,
 {
+    progress.set_style(ProgressStyle::default_spinner().template("{spinner} {prefix} {msg}"));
     progress.enable_steady_tick(120);
-    progress.set_message(&msg);
-    let result = f();
+    progress.set_prefix(msg);
+    let result = f(progress);
     progress.finish_and_clear();
     result
 }
 
 fn all_issues(
-    token: &str,
+    client: &Client,
+    pb: &ProgressBar,
     start: &Option<DateTime<Utc>>,
     end: &Option<DateTime<Utc>>,
     owner: &str,
     repo: &str,
 ) -> anyhow::Result<Vec<Issue>> {
-    repo::issues(token, end, &repo::Mode::Issues, owner, repo).map(|is| {
-        is.into_iter()
-            .filter(|i| {
-                let after = start.map(|s| i.created_at >= s).unwrap_or(true);
-                let before = end.map(|e| i.created_at <= e).unwrap_or(true);
-                after && before
-            })
-            .map(|i| Issue(issue_thread(i)))
-            .collect()
-    })
+    repo::issues(client, end, &repo::Mode::Issues, owner, repo, pb)
+        .map(|is| to_issues(is, start, end))
 }
 
 fn all_prs(
-    token: &str,
+    client: &Client,
+    pb: &ProgressBar,
     start: &Option<DateTime<Utc>>,
     end: &Option<DateTime<Utc>>,
     commits: bool,
@@ -595,24 +1034,111 @@ fn all_prs(
     } else {
         repo::Mode::PRs
     };
-    repo::issues(token, end, &mode, owner, repo).map(|is| {
-        is.into_iter()
-            .filter(|i| {
-                let after = start.map(|s| i.created_at >= s).unwrap_or(true);
-                let before = end.map(|e| i.created_at <= e).unwrap_or(true);
-                after && before
-            })
-            .map(|i| {
-                let merged = i.merged_at;
-                let commits = i.commits.as_ref().map(|cc| cc.total_count).unwrap_or(0);
-                let thread = issue_thread(i);
-                PR {
-                    thread,
-                    merged,
-                    commits,
-                }
-            })
-            .collect()
+    repo::issues(client, end, &mode, owner, repo, pb).map(|is| to_prs(is, start, end))
+}
+
+/// Was the thread opened within the given time range?
+fn within(i: &repo::Issue, start: &Option<DateTime<Utc>>, end: &Option<DateTime<Utc>>) -> bool {
+    let after = start.map(|s| i.created_at >= s).unwrap_or(true);
+    let before = end.map(|e| i.created_at <= e).unwrap_or(true);
+    after && before
+}
+
+fn to_issues<I>(is: I, start: &Option<DateTime<Utc>>, end: &Option<DateTime<Utc>>) -> Vec<Issue>
+where
+    I: IntoIterator<Item = repo::Issue>,
+{
+    is.into_iter()
+        .filter(|i| within(i, start, end))
+        .map(|i| Issue(issue_thread(i)))
+        .collect()
+}
+
+fn to_prs<I>(is: I, start: &Option<DateTime<Utc>>, end: &Option<DateTime<Utc>>) -> Vec<PR>
+where
+    I: IntoIterator<Item = repo::Issue>,
+{
+    is.into_iter()
+        .filter(|i| within(i, start, end))
+        .map(|i| {
+            let merged = i.merged_at;
+            let commits = i.commits.as_ref().map(|cc| cc.total_count).unwrap_or(0);
+            let author = ghost(&i.author);
+
+            // Reviews by the author themselves are just replies.
+            let reviews: Vec<&repo::Review> = i
+                .reviews
+                .iter()
+                .flat_map(|c| c.edges.iter().map(|n| &n.node))
+                .filter(|r| r.submitted_at.is_some() && ghost(&r.author) != author)
+                .collect();
+            let reviewers = reviews
+                .iter()
+                .map(|r| ghost(&r.author))
+                .collect::<Counter<_>>()
+                .into_map();
+            let first_review = reviews.iter().filter_map(|r| r.submitted_at).min();
+            let first_approval = reviews
+                .iter()
+                .filter(|r| r.state == repo::ReviewState::Approved)
+                .filter_map(|r| r.submitted_at)
+                .min();
+
+            PR {
+                thread: issue_thread(i),
+                merged,
+                commits,
+                reviewers,
+                first_review,
+                first_approval,
+            }
+        })
+        .collect()
+}
+
+/// Bring the local copy of a repository's threads, kept within `dir`, up to
+/// date. Only threads updated since the previous sync are fetched. Yields the
+/// number of Issues and Pull Requests that changed.
+pub fn sync_repo(
+    client: &Client,
+    dir: &Path,
+    owner: &str,
+    repo: &str,
+) -> anyhow::Result<(usize, usize)> {
+    let path = Store::path(dir, client.host(), owner, repo);
+    let mut store = Store::load(&path)?;
+    let counts = store
+        .sync(client, owner, repo)
+        .with_context(|| format!("{}/{}", owner, repo))?;
+    store.save(&path)?;
+    Ok(counts)
+}
+
+/// The [`Thread`](struct.Thread.html)s of a repository as saved by a previous
+/// [`sync_repo`](fn.sync_repo.html).
+pub fn stored_threads(
+    client: &Client,
+    dir: &Path,
+    start: &Option<DateTime<Utc>>,
+    end: &Option<DateTime<Utc>>,
+    owner: &str,
+    repo: &str,
+) -> anyhow::Result<Postings> {
+    let path = Store::path(dir, client.host(), owner, repo);
+    if !path.exists() {
+        return Err(anyhow!(
+            "No local data for {}/{}. Try `credit sync {}/{}` first.",
+            owner,
+            repo,
+            owner,
+            repo
+        ));
+    }
+
+    let store = Store::load(&path)?;
+    Ok(Postings {
+        issues: to_issues(store.issues.into_values(), start, end),
+        prs: to_prs(store.prs.into_values(), start, end),
     })
 }
 
@@ -624,39 +1150,104 @@ fn ghost(author: &Option<repo::Author>) -> String {
 }
 
 fn issue_thread(issue: repo::Issue) -> Thread {
+    let author = ghost(&issue.author);
+    let followed_up = issue.comments.followed_up
+        || issue
+            .reviews
+            .as_ref()
+            .map(|r| r.followed_up)
+            .unwrap_or(false);
     let comments: Vec<repo::Comment> = issue.comments.edges.into_iter().map(|n| n.node).collect();
+    let reviews: Vec<repo::Review> = issue
+        .reviews
+        .into_iter()
+        .flat_map(|c| c.edges.into_iter().map(|n| n.node))
+        .collect();
+    let labels = issue
+        .labels
+        .into_iter()
+        .flat_map(|c| c.edges.into_iter().map(|n| n.node.name))
+        .collect();
+    let reactions = issue.reaction_groups.unwrap_or_default();
+    let upvotes = reactions
+        .iter()
+        .filter(|g| g.content == repo::Reaction::ThumbsUp)
+        .map(|g| g.reactors.total_count)
+        .sum();
+    let reactors = reactions
+        .into_iter()
+        .flat_map(|g| g.reactors.edges.into_iter().map(|n| n.node.login))
+        .collect::<Counter<_>>()
+        .into_map();
+
+    // Submitted reviews are responses just as comments are: who made them,
+    // whether they're official, and when.
+    let responses: Vec<(String, bool, DateTime<Utc>)> = comments
+        .iter()
+        // Need to be careful, since the first physical response might have
+        // been from the Issue author.
+        .filter(|c| !c.author_association.is_author())
+        .map(|c| {
+            (
+                ghost(&c.author),
+                c.author_association.is_official(),
+                c.created_at,
+            )
+        })
+        .chain(reviews.iter().filter_map(|r| {
+            let who = ghost(&r.author);
+            let official = r.author_association.is_official();
+            r.submitted_at
+                .filter(|_| who != author)
+                .map(|t| (who, official, t))
+        }))
+        .sorted_by_key(|(_, _, t)| *t)
+        .collect();
 
-    // Need to be careful, since the first physical response might have been
-    // from the Issue author.
-    let first_comment = comments.iter().find(|c| !c.author_association.is_author());
-    let first_responder = first_comment.map(|c| ghost(&c.author));
-    let first_response = first_comment.map(|c| c.created_at);
+    let first = responses.first();
+    let first_responder = first.map(|(who, _, _)| who.clone());
+    let first_response = first.map(|(_, _, t)| *t);
     let first_official_response = comments
         .iter()
-        .find(|c| c.author_association.is_official())
-        .map(|c| c.created_at);
-    let comment_counts = comments
+        .filter(|c| c.author_association.is_official())
+        .map(|c| c.created_at)
+        .chain(responses.iter().filter(|r| r.1).map(|r| r.2))
+        .min();
+
+    // The line-by-line comments of a review count along with regular ones.
+    // Only those of submitted reviews are ever counted.
+    let mut comment_counts = comments
         .iter()
         .map(|c| ghost(&c.author))
         .collect::<Counter<_>>()
         .into_map();
+    for r in reviews.iter().filter(|r| r.comment_count > 0) {
+        *comment_counts.entry(ghost(&r.author)).or_insert(0) += r.comment_count;
+    }
 
     Thread {
-        author: ghost(&issue.author),
+        title: issue.title,
+        url: issue.url,
+        author,
         posted: issue.created_at,
         closed: issue.closed_at,
+        state_reason: issue.state_reason,
         first_responder,
         first_response,
         first_official_response,
         comments: comment_counts,
+        labels,
+        upvotes,
+        reactors,
+        followed_up,
     }
 }
 
 /// A curated list of the Top 100 users in a given location, ranked via their
 /// contribution counts and weighted by followers.
-pub fn user_contributions(token: &str, location: &str) -> anyhow::Result<UserContribs> {
-    let total_users = contribs::user_count(token, location)?.user_count;
-    let contributions = contribs::user_contributions(token, location)?
+pub fn user_contributions(client: &Client, location: &str) -> anyhow::Result<UserContribs> {
+    let total_users = contribs::user_count(client, location)?.user_count;
+    let contributions = contribs::user_contributions(client, location)?
         .into_iter()
         .sorted_by(|a, b| b.contribs().cmp(&a.contribs()))
         .take(500)
@@ -664,7 +1255,7 @@ pub fn user_contributions(token: &str, location: &str) -> anyhow::Result<UserCon
         .take(250)
         .sorted_by(|a, b| b.contribs().cmp(&a.contribs()))
         .take(100)
-        .map(|uc| User::from(uc))
+        .map(User::from)
         .collect();
 
     Ok(UserContribs {
@@ -686,6 +1277,71 @@ where
     a
 }
 
+/// How closely the upvotes of issues follow the time until something happened
+/// to them, among those it happened to.
+fn upvote_correlation<F>(issues: &[Issue], f: F) -> Option<f64>
+where
+    F: Fn(&Thread) -> Option<DateTime<Utc>>,
+{
+    let pairs: Vec<(f64, f64)> = issues
+        .iter()
+        .filter_map(|i| {
+            f(&i.0).map(|t| (i.0.upvotes as f64, (t - i.0.posted).num_seconds() as f64))
+        })
+        .collect();
+
+    rank_correlation(&pairs)
+}
+
+/// Spearman's rank correlation: how closely two measures rise and fall
+/// together, from -1 to 1. Unlike a plain correlation, a few extreme values
+/// (say an issue left open for years) can't dominate it.
+fn rank_correlation(pairs: &[(f64, f64)]) -> Option<f64> {
+    let xs = ranks(pairs.iter().map(|p| p.0).collect());
+    let ys = ranks(pairs.iter().map(|p| p.1).collect());
+    let n = pairs.len() as f64;
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = ys.iter().sum::<f64>() / n;
+
+    let (cov, var_x, var_y) =
+        xs.iter()
+            .zip(ys.iter())
+            .fold((0.0, 0.0, 0.0), |(cov, var_x, var_y), (x, y)| {
+                let (dx, dy) = (x - mean_x, y - mean_y);
+                (cov + dx * dy, var_x + dx * dx, var_y + dy * dy)
+            });
+
+    // Nothing can be said if either measure never varies.
+    if var_x == 0.0 || var_y == 0.0 {
+        None
+    } else {
+        Some(cov / (var_x * var_y).sqrt())
+    }
+}
+
+/// The rank of each value among the others. Tied values share the average of
+/// their ranks.
+fn ranks(values: Vec<f64>) -> Vec<f64> {
+    let order: Vec<usize> = (0..values.len())
+        .sorted_by(|&a, &b| values[a].total_cmp(&values[b]))
+        .collect();
+    let mut ranks = vec![0.0; values.len()];
+    let mut start = 0;
+
+    while start < order.len() {
+        let end = order[start..]
+            .iter()
+            .take_while(|&&i| values[i] == values[order[start]])
+            .count()
+            + start;
+        let rank = (start + end + 1) as f64 / 2.0;
+        order[start..end].iter().for_each(|&i| ranks[i] = rank);
+        start = end;
+    }
+
+    ranks
+}
+
 fn percent(a: usize, b: usize) -> f64 {
     100.0 * (a as f64) / (b as f64)
 }
@@ -702,7 +1358,189 @@ fn hashmap_extend() {
     }
 
     let third = hashmap_combine(first, second);
-    let elems: Vec<usize> = third.values().map(|v| *v).collect();
+    let elems: Vec<usize> = third.values().copied().collect();
 
     assert_eq!(vec![2, 2, 2, 2, 2, 2], elems);
 }
+
+#[test]
+fn offline_statistics() {
+    use crate::fixtures;
+
+    let issues_1 = r#"{"data": {"repository": {"issues": {
+      "pageInfo": {"hasNextPage": true, "endCursor": "c1"},
+      "edges": [{"node": {
+        "number": 1,
+        "author": {"login": "alice"},
+        "createdAt": "2021-01-01T00:00:00Z",
+        "updatedAt": "2021-01-01T00:00:00Z",
+        "closedAt": "2021-01-03T00:00:00Z",
+        "stateReason": "NOT_PLANNED",
+        "comments": {"edges": [
+          {"node": {"author": {"login": "alice"}, "authorAssociation": "AUTHOR", "createdAt": "2021-01-01T01:00:00Z"}},
+          {"node": {"author": {"login": "bob"}, "authorAssociation": "OWNER", "createdAt": "2021-01-01T02:00:00Z"}}
+        ]}
+      }}]
+    }}}}"#;
+    let issues_2 = r#"{"data": {"repository": {"issues": {
+      "pageInfo": {"hasNextPage": false, "endCursor": null},
+      "edges": [{"node": {
+        "number": 2,
+        "title": "Do the thing",
+        "url": "https://github.com/a/b/issues/2",
+        "author": null,
+        "createdAt": "2021-02-01T00:00:00Z",
+        "updatedAt": "2021-02-01T00:00:00Z",
+        "closedAt": null,
+        "reactionGroups": [
+          {"content": "THUMBS_UP", "reactors": {"totalCount": 2, "edges": [
+            {"node": {"login": "alice"}}, {"node": {"login": "dave"}}
+          ]}},
+          {"content": "HEART", "reactors": {"totalCount": 1, "edges": [{"node": {"login": "dave"}}]}}
+        ],
+        "comments": {"edges": []}
+      }}]
+    }}}}"#;
+    let prs = r#"{"data": {"repository": {"pullRequests": {
+      "pageInfo": {"hasNextPage": false, "endCursor": null},
+      "edges": [{"node": {
+        "number": 3,
+        "author": {"login": "carol"},
+        "createdAt": "2021-01-05T00:00:00Z",
+        "updatedAt": "2021-01-05T00:00:00Z",
+        "closedAt": "2021-01-06T00:00:00Z",
+        "mergedAt": "2021-01-06T00:00:00Z",
+        "comments": {"edges": [
+          {"node": {"author": {"login": "bob"}, "authorAssociation": "MEMBER", "createdAt": "2021-01-05T03:00:00Z"}}
+        ]}
+      }}]
+    }}}}"#;
+
+    let client = Client::github("fake").with_transport(
+        Fixtures::new()
+            .respond("issues(first: 1)", fixtures::sizes(2, 1))
+            .respond("issues(first: 1)", fixtures::sizes(2, 1))
+            .respond("issues(", issues_1)
+            .respond("issues(", issues_2)
+            .respond("pullRequests(", prs),
+    );
+    let pb = ProgressBar::hidden();
+    let stats = repo_threads(&client, &pb, &pb, false, &None, &None, "a", "b")
+        .unwrap()
+        .statistics();
+
+    assert_eq!(2, stats.all_issues);
+    assert_eq!(1, stats.all_closed_issues);
+    assert_eq!((0, 1), (stats.issues_completed, stats.issues_not_planned));
+    assert_eq!(
+        Duration::from_secs(2 * 24 * 3600),
+        stats.issue_not_planned_time.unwrap().median
+    );
+    assert_eq!(1, stats.issues_with_official_responses);
+    assert_eq!(1, stats.prs_merged);
+    assert_eq!(Some(&2), stats.commentors.get("bob"));
+    assert_eq!(Some(&2), stats.reactors.get("dave"));
+    assert_eq!(2, stats.most_upvoted[0].upvotes);
+    assert_eq!(
+        Duration::from_secs(2 * 3600),
+        stats.issue_official_first_resp_time.unwrap().median
+    );
+}
+
+#[test]
+fn pr_reviews() {
+    let review = |login: &str, state: &str, at: &str, comments: usize| {
+        format!(
+            r#"{{"node": {{"author": {{"login": "{}"}}, "authorAssociation": "MEMBER",
+              "state": "{}", "submittedAt": {}, "commentCount": {}}}}}"#,
+            login, state, at, comments
+        )
+    };
+    let pr = format!(
+        r#"{{"number": 1, "author": {{"login": "carol"}},
+          "createdAt": "2021-01-05T00:00:00Z", "updatedAt": "2021-01-05T00:00:00Z",
+          "closedAt": null, "mergedAt": null,
+          "comments": {{"edges": [
+            {{"node": {{"author": {{"login": "dave"}}, "authorAssociation": "NONE", "createdAt": "2021-01-05T05:00:00Z"}}}}
+          ]}},
+          "reviews": {{"edges": [{}, {}, {}, {}], "followedUp": true}}}}"#,
+        review("carol", "COMMENTED", "\"2021-01-05T01:00:00Z\"", 1),
+        review("bob", "PENDING", "null", 0),
+        review("bob", "CHANGES_REQUESTED", "\"2021-01-05T02:00:00Z\"", 2),
+        review("erin", "APPROVED", "\"2021-01-05T04:00:00Z\"", 0),
+    );
+    let issue: repo::Issue = serde_json::from_str(&pr).unwrap();
+    let pr = to_prs(vec![issue], &None, &None).pop().unwrap();
+
+    // The author's own review and the pending one don't count.
+    assert_eq!(2, pr.reviewers.len());
+    assert_eq!(Some("bob".to_string()), pr.thread.first_responder);
+    assert_eq!(pr.first_review, pr.thread.first_response);
+    assert_eq!(
+        "2021-01-05T04:00:00Z".parse::<DateTime<Utc>>().ok(),
+        pr.first_approval
+    );
+    assert_eq!(Some(&1), pr.reviewers.get("bob"));
+    assert_eq!(Some(&2), pr.thread.comments.get("bob"));
+    assert_eq!(None, pr.thread.comments.get("erin"));
+    assert!(pr.thread.followed_up);
+}
+
+#[test]
+fn label_breakdown() {
+    let issue = |n: u32, labels: &[&str], closed: &str| {
+        let labels = labels
+            .iter()
+            .map(|l| format!(r#"{{"node": {{"name": "{}"}}}}"#, l))
+            .join(", ");
+        let json = format!(
+            r#"{{"number": {}, "author": null,
+              "createdAt": "2021-01-01T00:00:00Z", "updatedAt": "2021-01-01T00:00:00Z",
+              "closedAt": {}, "comments": {{"edges": []}}, "labels": {{"edges": [{}]}}}}"#,
+            n, closed, labels
+        );
+        serde_json::from_str::<repo::Issue>(&json).unwrap()
+    };
+    let issues = vec![
+        issue(1, &["bug"], "\"2021-01-03T00:00:00Z\""),
+        issue(2, &["Bug", "wontfix"], "\"2021-01-02T00:00:00Z\""),
+        issue(3, &["feature"], "null"),
+        issue(4, &[], "null"),
+    ];
+    let postings = Postings {
+        issues: to_issues(issues, &None, &None),
+        prs: vec![],
+    };
+
+    // "bug" and "Bug" are one label, as they are when filtering.
+    let labels = postings.label_statistics();
+    assert_eq!(
+        vec!["bug", "feature", "wontfix"],
+        labels.keys().collect::<Vec<_>>()
+    );
+    assert_eq!(2, labels["bug"].threads);
+
+    let bugs = postings.with_labels(&["BUG".to_string()], &["wontfix".to_string()]);
+    assert_eq!(1, bugs.issues.len());
+
+    let labels = bugs.label_statistics();
+    let bug = labels.get("bug").unwrap();
+    assert_eq!((1, 1), (bug.threads, bug.closed));
+    assert_eq!(
+        Duration::from_secs(2 * 24 * 3600),
+        bug.close_time.as_ref().unwrap().median
+    );
+    assert!(bug.first_resp_time.is_none());
+}
+
+#[test]
+fn upvote_correlations() {
+    let same = [(1.0, 10.0), (2.0, 20.0), (3.0, 300.0), (4.0, 4000.0)];
+    assert_eq!(Some(1.0), rank_correlation(&same));
+
+    let opposite = [(0.0, 50.0), (5.0, 10.0), (5.0, 10.0), (9.0, 1.0)];
+    assert_eq!(Some(-1.0), rank_correlation(&opposite));
+
+    assert_eq!(vec![1.0, 2.5, 2.5, 4.0], ranks(vec![3.0, 7.0, 7.0, 8.0]));
+    assert_eq!(None, rank_correlation(&[(1.0, 2.0), (1.0, 3.0)]));
+}
@@ -4,15 +4,14 @@ use crate::github;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-const LIMIT_QUERY: &str = "{ \
-     \"query\": \"{ \
-       rateLimit { \
-         limit \
-         remaining \
-         resetAt \
-       } \
-     }\" \
-   }";
+const LIMIT_QUERY: &str = "
+query {
+  rateLimit {
+    limit
+    remaining
+    resetAt
+  }
+}";
 
 #[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -29,7 +28,8 @@ struct RateLimitQuery {
 }
 
 /// Discover the remaining API quota for the given token.
-pub fn rate_limit(token: &str) -> anyhow::Result<RateLimit> {
-    let result: RateLimitQuery = github::lookup(token, LIMIT_QUERY.to_string())?;
+pub fn rate_limit(client: &github::Client) -> anyhow::Result<RateLimit> {
+    let request = github::Request::new(LIMIT_QUERY, serde_json::json!({}));
+    let result: RateLimitQuery = github::lookup(client, &request)?;
     Ok(result.rate_limit)
 }
@@ -1,19 +1,264 @@
 //! A tool for measuring repository contributions.
 
 use anyhow::{anyhow, Context};
-use chrono::{DateTime, NaiveDate, Utc};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
 use gumdrop::{Options, ParsingStyle};
 use indicatif::{MultiProgress, ProgressBar};
 use itertools::Itertools;
 use rayon::prelude::*;
 use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
 use std::io::{self, Read};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use std::{process, thread};
 
+/// The environment variable that can override the API endpoint.
+const API_URL_VAR: &str = "CREDIT_API_URL";
+
 /// Config that can be set in a `credit.toml` file.
 #[derive(Deserialize, Default)]
 struct Config {
     token: Option<String>,
+    /// Further tokens to rotate through as quotas run low.
+    #[serde(default)]
+    tokens: Vec<String>,
+    /// Authenticate as a Github App rather than with a personal token.
+    app: Option<AppConfig>,
+    /// The GraphQL endpoint to query, for Github Enterprise Server.
+    api_url: Option<String>,
+    /// Settings specific to certain API hosts, keyed by hostname.
+    #[serde(default)]
+    hosts: HashMap<String, Host>,
+    /// How many hours cached responses remain valid for.
+    cache_ttl: Option<u64>,
+    #[serde(default)]
+    network: NetworkConfig,
+}
+
+/// Connection settings that can be set in the `[network]` section of a
+/// `credit.toml` file. Times are in seconds, and `0` disables a timeout.
+#[derive(Deserialize, Default)]
+struct NetworkConfig {
+    proxy: Option<String>,
+    no_proxy: Option<String>,
+    ca_bundle: Option<PathBuf>,
+    connect_timeout: Option<u64>,
+    timeout: Option<u64>,
+    /// In bytes per second.
+    low_speed_limit: Option<u32>,
+    low_speed_time: Option<u64>,
+    /// Most requests to have in flight at once.
+    concurrency: Option<usize>,
+    /// Most requests to start per second.
+    rate: Option<u32>,
+}
+
+/// Config for a single API host.
+#[derive(Deserialize, Default)]
+struct Host {
+    token: Option<String>,
+    #[serde(default)]
+    tokens: Vec<String>,
+    app: Option<AppConfig>,
+}
+
+/// The `[app]` section of a `credit.toml` file.
+#[derive(Deserialize)]
+struct AppConfig {
+    id: u64,
+    installation_id: u64,
+    /// The App's PEM-encoded private key.
+    private_key: PathBuf,
+}
+
+impl AppConfig {
+    fn load(&self) -> anyhow::Result<credit::App> {
+        let pem = std::fs::read(&self.private_key).with_context(|| {
+            format!(
+                "Couldn't read Github App key {}",
+                self.private_key.display()
+            )
+        })?;
+        credit::App::new(self.id, self.installation_id, &pem)
+    }
+}
+
+/// A client for the given credentials, if there are any. A single `token` and
+/// any extra `tokens` are pooled together, and take precedence over an App.
+fn authenticate(
+    url: &str,
+    token: &Option<String>,
+    tokens: &[String],
+    app: &Option<AppConfig>,
+) -> anyhow::Result<Option<credit::Client>> {
+    let pool: Vec<String> = token.iter().chain(tokens).cloned().collect();
+
+    match app {
+        _ if !pool.is_empty() => Ok(Some(credit::Client::pooled(url, pool))),
+        Some(app) => Ok(Some(credit::Client::as_app(url, app.load()?))),
+        None => Ok(None),
+    }
+}
+
+impl Config {
+    /// Build a client, preferring command-line values over the environment,
+    /// and the environment over the config file.
+    fn client(
+        &self,
+        command: &Command,
+        tape: &Tape,
+        curl: &Arc<credit::Curl>,
+    ) -> anyhow::Result<credit::Client> {
+        let url = command
+            .api_url()
+            .or_else(|| std::env::var(API_URL_VAR).ok())
+            .or_else(|| self.api_url.clone())
+            .unwrap_or_else(|| credit::V4_URL.to_string());
+        let tokens = command.tokens();
+        let client = if tokens.is_empty() {
+            let from_host = self
+                .hosts
+                .get(credit::host(&url))
+                .map(|h| authenticate(&url, &h.token, &h.tokens, &h.app))
+                .transpose()?
+                .flatten();
+
+            match from_host {
+                Some(client) => client,
+                None => match authenticate(&url, &self.token, &self.tokens, &self.app)? {
+                    Some(client) => client,
+                    // Replayed responses don't need a real token.
+                    None if matches!(tape, Tape::Replay(_)) => credit::Client::pooled(&url, vec![]),
+                    None => return Err(anyhow!("No token given!")),
+                },
+            }
+        } else {
+            credit::Client::pooled(&url, tokens)
+        };
+
+        let client = match tape {
+            Tape::Live => client.with_transport(curl.clone()),
+            Tape::Record(dir) => {
+                client.with_transport(credit::Recorder::new(dir.clone(), curl.clone())?)
+            }
+            Tape::Replay(dir) => client.with_transport(credit::Replay::new(dir.clone())),
+        };
+
+        let (concurrency, rate) = command.pacing();
+        let scheduler = credit::Scheduler::new(
+            concurrency
+                .or(self.network.concurrency)
+                .unwrap_or(credit::DEFAULT_CONCURRENCY),
+        );
+        let scheduler = match rate.or(self.network.rate) {
+            Some(r) if r > 0 => scheduler.per_second(r),
+            _ => scheduler,
+        };
+        let client = client.with_scheduler(scheduler);
+
+        // Recordings can only be replayed if pages are asked for at the same
+        // sizes, so those always start afresh.
+        let client = match tape {
+            Tape::Live => client.with_page_sizes(credit::PageSizes::load(page_sizes_path()?)),
+            _ => client,
+        };
+
+        let client = match command.resuming() {
+            None => client,
+            Some(resume) => client.with_checkpoints(checkpoint_dir()?, resume),
+        };
+
+        let client = match command.max_cost() {
+            None => client,
+            Some(points) => client.with_budget(points),
+        };
+
+        // Recordings should hold every exchange, not just those the cache missed.
+        match command.caching() {
+            _ if !matches!(tape, Tape::Live) => Ok(client),
+            None => Ok(client),
+            Some(false) => Ok(client.with_cache(self.cache()?)),
+            Some(true) => Ok(client.with_cache(self.cache()?.refreshing())),
+        }
+    }
+
+    /// Connection settings. `CREDIT_*` environment variables take precedence
+    /// over the config file, which takes precedence over the standard proxy
+    /// variables like `HTTPS_PROXY`. Variables are looked up with `var`,
+    /// normally from the environment.
+    fn network(&self, var: &dyn Fn(&str) -> Option<String>) -> anyhow::Result<credit::Network> {
+        let net = &self.network;
+        let default = credit::Network::default();
+        let secs = |name: &str, conf: Option<u64>, def: Option<Duration>| {
+            env_parse(var, name).map(|v| v.or(conf)).map(|v| match v {
+                None => def,
+                Some(0) => None,
+                Some(s) => Some(Duration::from_secs(s)),
+            })
+        };
+
+        Ok(credit::Network {
+            proxy: env(var, &["CREDIT_PROXY"])
+                .or_else(|| net.proxy.clone())
+                .or_else(|| {
+                    env(
+                        var,
+                        &["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"],
+                    )
+                }),
+            no_proxy: env(var, &["CREDIT_NO_PROXY"])
+                .or_else(|| net.no_proxy.clone())
+                .or_else(|| env(var, &["NO_PROXY", "no_proxy"])),
+            ca_bundle: env(var, &["CREDIT_CA_BUNDLE"])
+                .map(PathBuf::from)
+                .or_else(|| net.ca_bundle.clone()),
+            connect_timeout: secs(
+                "CREDIT_CONNECT_TIMEOUT",
+                net.connect_timeout,
+                default.connect_timeout,
+            )?,
+            timeout: secs("CREDIT_TIMEOUT", net.timeout, default.timeout)?,
+            low_speed_limit: env_parse(var, "CREDIT_LOW_SPEED_LIMIT")?
+                .or(net.low_speed_limit)
+                .unwrap_or(default.low_speed_limit),
+            low_speed_time: env_parse(var, "CREDIT_LOW_SPEED_TIME")?
+                .or(net.low_speed_time)
+                .map(Duration::from_secs)
+                .unwrap_or(default.low_speed_time),
+        })
+    }
+
+    /// The on-disk cache of Github responses.
+    fn cache(&self) -> anyhow::Result<credit::Cache> {
+        let dir = xdg::BaseDirectories::with_prefix("credit")?
+            .get_cache_home()
+            .join("responses");
+        let ttl = self
+            .cache_ttl
+            .map(|hours| Duration::from_secs(hours * 60 * 60))
+            .unwrap_or(credit::DEFAULT_TTL);
+
+        Ok(credit::Cache::new(dir, ttl))
+    }
+}
+
+/// The first of the given environment variables that is set and non-empty.
+fn env(var: &dyn Fn(&str) -> Option<String>, names: &[&str]) -> Option<String> {
+    names.iter().find_map(|v| var(v).filter(|s| !s.is_empty()))
+}
+
+/// Parse an environment variable, if it's set.
+fn env_parse<T>(var: &dyn Fn(&str) -> Option<String>, name: &str) -> anyhow::Result<Option<T>>
+where
+    T: std::str::FromStr,
+    T::Err: std::error::Error + Send + std::marker::Sync + 'static,
+{
+    env(var, &[name])
+        .map(|v| v.parse())
+        .transpose()
+        .with_context(|| format!("Couldn't parse {}", name))
 }
 
 /// A tool for measuring repository contributions.
@@ -25,30 +270,104 @@ struct Args {
     /// Print the current version of credit.
     version: bool,
 
+    /// Write every exchange with Github to a directory.
+    #[options(no_short, meta = "DIR")]
+    record: Option<PathBuf>,
+
+    /// Answer requests from a --record directory instead of Github.
+    #[options(no_short, meta = "DIR")]
+    replay: Option<PathBuf>,
+
+    /// Print the timing of each request to Github.
+    #[options(no_short)]
+    verbose: bool,
+
     /// Command to perform.
     #[options(command)]
     command: Option<Command>,
 }
 
+/// Where exchanges with Github are recorded to or replayed from, if anywhere.
+enum Tape {
+    Live,
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
 #[derive(Options)]
 enum Command {
     /// Analyse repository contributions.
     Repo(Repo),
+    /// Predict the API cost and duration of a `repo` run.
+    Estimate(Estimate),
     /// Find the most active users in a given area.
     Users(Users),
     /// Check the Github API for remaining rate limit allowance.
     Limit(Limit),
     /// Print markdown of JSON from a previous run of `credit repo --json`.
     Json(Json),
+    /// Keep a local copy of repositories' Issues and PRs up to date.
+    Sync(Sync),
+    /// Inspect or clear the cache of Github responses.
+    Cache(Cache),
 }
 
 impl Command {
-    fn token(&self) -> Option<String> {
+    fn tokens(&self) -> Vec<String> {
         match self {
             Command::Repo(r) => r.token.clone(),
+            Command::Estimate(e) => e.token.clone(),
             Command::Users(u) => u.token.clone(),
             Command::Limit(l) => l.token.clone(),
-            Command::Json(_) => None,
+            Command::Sync(s) => s.token.clone(),
+            Command::Json(_) | Command::Cache(_) => vec![],
+        }
+    }
+
+    fn api_url(&self) -> Option<String> {
+        match self {
+            Command::Repo(r) => r.api_url.clone(),
+            Command::Estimate(e) => e.api_url.clone(),
+            Command::Users(u) => u.api_url.clone(),
+            Command::Limit(l) => l.api_url.clone(),
+            Command::Sync(s) => s.api_url.clone(),
+            Command::Json(_) | Command::Cache(_) => None,
+        }
+    }
+
+    /// Should responses be cached, and if so, should existing entries be
+    /// ignored?
+    fn caching(&self) -> Option<bool> {
+        match self {
+            Command::Repo(r) if !r.no_cache => Some(r.refresh),
+            Command::Users(u) if !u.no_cache => Some(u.refresh),
+            _ => None,
+        }
+    }
+
+    /// Should the progress of crawls be saved, and if so, should an earlier
+    /// run's progress be picked up?
+    fn resuming(&self) -> Option<bool> {
+        match self {
+            Command::Repo(r) if !r.local && !r.sync => Some(r.resume),
+            _ => None,
+        }
+    }
+
+    /// Overrides for the request scheduler's concurrency and rate.
+    fn pacing(&self) -> (Option<usize>, Option<u32>) {
+        match self {
+            Command::Repo(r) => (r.concurrency, r.rate),
+            Command::Estimate(e) => (e.concurrency, e.rate),
+            _ => (None, None),
+        }
+    }
+
+    fn max_cost(&self) -> Option<u32> {
+        match self {
+            Command::Repo(r) => r.max_cost,
+            Command::Users(u) => u.max_cost,
+            _ => None,
         }
     }
 }
@@ -58,8 +377,11 @@ impl Command {
 struct Repo {
     /// Print this help text.
     help: bool,
-    /// Github personal access token.
-    token: Option<String>,
+    /// Github personal access token. Give several to rotate between them.
+    token: Vec<String>,
+    /// GraphQL endpoint to query (e.g. for Github Enterprise Server).
+    #[options(no_short, meta = "URL")]
+    api_url: Option<String>,
     /// Look up Pull Request commit counts as well.
     commits: bool,
     /// Only consider contributions / comments after the given date.
@@ -68,10 +390,44 @@ struct Repo {
     /// Only consider contributions / comments before the given date.
     #[options(parse(try_from_str = "datetime"), meta = "YYYY-MM-DD")]
     end: Option<DateTime<Utc>>,
+    /// Only consider threads with this label (can pass multiple times).
+    #[options(no_short, meta = "LABEL")]
+    label: Vec<String>,
+    /// Ignore threads with this label (can pass multiple times).
+    #[options(no_short, meta = "LABEL")]
+    exclude_label: Vec<String>,
+    /// Break the statistics down by label.
+    #[options(no_short)]
+    by_label: bool,
     /// Output as JSON.
     json: bool,
-    /// Fetch Issues first, then PRs.
-    serial: bool,
+    /// Don't read or write cached Github responses.
+    #[options(no_short)]
+    no_cache: bool,
+    /// Ignore cached Github responses, but cache the new ones.
+    #[options(no_short)]
+    refresh: bool,
+    /// Most requests to have in flight at once, across all repositories.
+    #[options(no_short, meta = "N")]
+    concurrency: Option<usize>,
+    /// Most requests to start per second.
+    #[options(no_short, meta = "N")]
+    rate: Option<u32>,
+    /// Stop fetching before spending more than this many API points.
+    #[options(no_short, meta = "POINTS")]
+    max_cost: Option<u32>,
+    /// Report from the local copy made by `credit sync`, instead of Github.
+    #[options(no_short)]
+    local: bool,
+    /// Sync the local copy first, then report from it.
+    #[options(no_short)]
+    sync: bool,
+    /// Carry on from where an interrupted run left off.
+    #[options(no_short)]
+    resume: bool,
+    /// If interrupted, report on what was fetched so far.
+    #[options(no_short)]
+    partial: bool,
     #[options(default = "10")]
     limit: usize,
     /// A Github repository to check (can pass multiple times).
@@ -79,18 +435,55 @@ struct Repo {
     repos: Vec<(String, String)>,
 }
 
+/// Predict the API cost and duration of a `repo` run.
+#[derive(Options)]
+struct Estimate {
+    /// Print this help text.
+    help: bool,
+    /// Github personal access token. Give several to rotate between them.
+    token: Vec<String>,
+    /// GraphQL endpoint to query (e.g. for Github Enterprise Server).
+    #[options(no_short, meta = "URL")]
+    api_url: Option<String>,
+    /// Look up Pull Request commit counts as well.
+    commits: bool,
+    /// Most requests to have in flight at once, across all repositories.
+    #[options(no_short, meta = "N")]
+    concurrency: Option<usize>,
+    /// Most requests to start per second.
+    #[options(no_short, meta = "N")]
+    rate: Option<u32>,
+    /// Output as JSON.
+    json: bool,
+    /// A Github repository to check (can pass multiple times).
+    #[options(free, parse(try_from_str = "split_repo"))]
+    repos: Vec<(String, String)>,
+}
+
 /// Find the most active users in a given area.
 #[derive(Options)]
 struct Users {
     /// Print this help text.
     help: bool,
-    /// Github personal access token.
-    token: Option<String>,
+    /// Github personal access token. Give several to rotate between them.
+    token: Vec<String>,
+    /// GraphQL endpoint to query (e.g. for Github Enterprise Server).
+    #[options(no_short, meta = "URL")]
+    api_url: Option<String>,
     /// The country to check.
     #[options(required)]
     location: String,
     /// Output as JSON.
     json: bool,
+    /// Stop fetching before spending more than this many API points.
+    #[options(no_short, meta = "POINTS")]
+    max_cost: Option<u32>,
+    /// Don't read or write cached Github responses.
+    #[options(no_short)]
+    no_cache: bool,
+    /// Ignore cached Github responses, but cache the new ones.
+    #[options(no_short)]
+    refresh: bool,
 }
 
 /// Check the Github API for remaining rate limit allowance.
@@ -98,8 +491,11 @@ struct Users {
 struct Limit {
     /// Print this help text.
     help: bool,
-    /// Github personal access token.
-    token: Option<String>,
+    /// Github personal access token. Give several to rotate between them.
+    token: Vec<String>,
+    /// GraphQL endpoint to query (e.g. for Github Enterprise Server).
+    #[options(no_short, meta = "URL")]
+    api_url: Option<String>,
 }
 
 /// Accept JSON from a previous run of `credit` through `stdin`, and print
@@ -112,6 +508,32 @@ struct Json {
     commits: bool,
 }
 
+/// Keep a local copy of repositories' Issues and PRs up to date.
+#[derive(Options)]
+struct Sync {
+    /// Print this help text.
+    help: bool,
+    /// Github personal access token. Give several to rotate between them.
+    token: Vec<String>,
+    /// GraphQL endpoint to query (e.g. for Github Enterprise Server).
+    #[options(no_short, meta = "URL")]
+    api_url: Option<String>,
+    /// A Github repository to sync (can pass multiple times).
+    #[options(free, parse(try_from_str = "split_repo"))]
+    repos: Vec<(String, String)>,
+}
+
+/// Inspect or clear the cache of Github responses.
+#[derive(Options)]
+struct Cache {
+    /// Print this help text.
+    help: bool,
+    /// Delete cached responses.
+    clear: bool,
+    /// Only delete responses that have expired.
+    expired: bool,
+}
+
 fn main() {
     let args = Args::parse_args_or_exit(ParsingStyle::AllOptions);
 
@@ -124,11 +546,22 @@ fn main() {
             eprintln!("{}", Args::usage());
             std::process::exit(1);
         }
-        Some(cmd) => report(work(cmd)),
+        Some(cmd) => {
+            let tape = match (args.record, args.replay) {
+                (None, None) => Tape::Live,
+                (Some(dir), None) => Tape::Record(dir),
+                (None, Some(dir)) => Tape::Replay(dir),
+                (Some(_), Some(_)) => {
+                    eprintln!("--record and --replay can't be used together.");
+                    process::exit(1)
+                }
+            };
+            report(work(cmd, &tape, args.verbose))
+        }
     }
 }
 
-fn work(command: Command) -> anyhow::Result<String> {
+fn work(command: Command, tape: &Tape, verbose: bool) -> anyhow::Result<String> {
     let mut config_path = xdg::BaseDirectories::new()?.get_config_home();
     config_path.push("credit.toml");
     let config: Config = std::fs::read_to_string(config_path)
@@ -136,21 +569,26 @@ fn work(command: Command) -> anyhow::Result<String> {
         .and_then(|s| toml::from_str(&s).ok())
         .unwrap_or_default();
 
-    match command {
-        Command::Limit(_) => match command.token().or(config.token) {
-            None => Err(anyhow!("No token given!")),
-            Some(token) => limit(&token),
-        },
-        Command::Repo(ref r) => match command.token().or(config.token) {
-            None => Err(anyhow!("No token given!")),
-            Some(token) => repo(&token, r),
-        },
-        Command::Users(ref u) => match command.token().or(config.token) {
-            None => Err(anyhow!("No token given!")),
-            Some(token) => users(&token, u),
-        },
+    // Shared by every client, so that connections are reused throughout.
+    let curl =
+        Arc::new(credit::Curl::new(config.network(&|v| std::env::var(v).ok())?).verbose(verbose));
+
+    let result = match command {
+        Command::Limit(_) => limit(&config.client(&command, tape, &curl)?),
+        Command::Repo(ref r) => repo(&config.client(&command, tape, &curl)?, r),
+        Command::Estimate(ref e) => estimate(&config.client(&command, tape, &curl)?, e),
+        Command::Users(ref u) => users(&config.client(&command, tape, &curl)?, u),
+        Command::Sync(ref s) => sync(&config.client(&command, tape, &curl)?, s),
         Command::Json(j) => json(j),
+        Command::Cache(c) => cache(&config.cache()?, c),
+    };
+
+    let stats = curl.stats();
+    if verbose && stats.requests > 0 {
+        eprintln!("Network: {}.", stats);
     }
+
+    result
 }
 
 /// Report results and exit with the appropriate code.
@@ -158,14 +596,57 @@ fn report(result: anyhow::Result<String>) {
     match result {
         Ok(result) => println!("{}", result),
         Err(e) => {
-            eprintln!("{}", e);
+            eprintln!("{}", diagnose(&e));
             process::exit(1)
         }
     }
 }
 
-fn users(token: &str, u: &Users) -> anyhow::Result<String> {
-    let users = credit::user_contributions(token, &u.location)?;
+/// A human-friendly explanation of what went wrong, including advice for
+/// errors reported by the Github API.
+fn diagnose(e: &anyhow::Error) -> String {
+    match e.downcast_ref::<credit::QueryErrors>() {
+        None => format!("{:#}", e),
+        Some(qe) => {
+            let context = e
+                .chain()
+                .take_while(|c| !c.is::<credit::QueryErrors>())
+                .join(": ");
+            let details = qe.errors.iter().map(|e| format!("  - {}", e)).join("\n");
+            let prefix = if context.is_empty() {
+                String::new()
+            } else {
+                format!("{}: ", context)
+            };
+            format!("{}{}\n{}", prefix, qe.kind().advice(), details)
+        }
+    }
+}
+
+/// A summary of the API points spent, for stderr.
+fn cost_summary(client: &credit::Client, meta: &credit::Meta) -> String {
+    let total = client.total_cost();
+    let mut lines = vec![format!("API cost: {}.", total)];
+
+    if meta.costs.len() > 1 {
+        for (name, cost) in meta.costs.iter() {
+            lines.push(format!("  - {}: {} points", name, cost.points));
+        }
+    }
+
+    if total.over_budget {
+        lines.push("Stopped early to stay within --max-cost. Results are incomplete.".to_string());
+    }
+
+    lines.join("\n")
+}
+
+fn users(client: &credit::Client, u: &Users) -> anyhow::Result<String> {
+    let users = credit::user_contributions(client, &u.location)?;
+
+    if client.total_cost().requests > 0 {
+        eprintln!("{}", cost_summary(client, &credit::Meta::default()));
+    }
 
     if u.json {
         let json = serde_json::to_string(&users)?;
@@ -183,17 +664,143 @@ fn json(j: Json) -> anyhow::Result<String> {
     Ok(stats.report("Unknown Project", 10, j.commits))
 }
 
-fn limit(token: &str) -> anyhow::Result<String> {
-    let rl = credit::rate_limit(token)?;
+/// Where the page sizes that Github coped with are remembered between runs.
+fn page_sizes_path() -> anyhow::Result<PathBuf> {
+    let path = xdg::BaseDirectories::with_prefix("credit")?
+        .get_cache_home()
+        .join("page-sizes.json");
+    Ok(path)
+}
+
+/// Where the progress of interrupted `credit repo` runs is kept.
+fn checkpoint_dir() -> anyhow::Result<PathBuf> {
+    let dir = xdg::BaseDirectories::with_prefix("credit")?
+        .get_cache_home()
+        .join("checkpoints");
+    Ok(dir)
+}
+
+/// Where `credit sync` keeps its local copies of repositories.
+fn store_dir() -> anyhow::Result<PathBuf> {
+    let dir = xdg::BaseDirectories::with_prefix("credit")?
+        .get_data_home()
+        .join("repos");
+    Ok(dir)
+}
+
+fn sync(client: &credit::Client, s: &Sync) -> anyhow::Result<String> {
+    if s.repos.is_empty() {
+        return Err(anyhow!("No repositories given!"));
+    }
+
+    let dir = store_dir()?;
+    let lines = s
+        .repos
+        .iter()
+        .map(|(owner, repo)| {
+            let pb = ProgressBar::new_spinner();
+            pb.enable_steady_tick(120);
+            pb.set_message(&format!("Syncing {}/{}...", owner, repo));
+            let result = credit::sync_repo(client, &dir, owner, repo);
+            pb.finish_and_clear();
+
+            match result {
+                Ok((issues, prs)) => format!(
+                    "{}/{}: {} Issues and {} Pull Requests updated.",
+                    owner, repo, issues, prs
+                ),
+                Err(e) => diagnose(&e),
+            }
+        })
+        .join("\n");
+
+    Ok(lines)
+}
+
+/// Look up threads from the local copy of a repository, syncing it first if
+/// asked to.
+fn local_threads(
+    client: &credit::Client,
+    pb: &ProgressBar,
+    r: &Repo,
+    owner: &str,
+    repo: &str,
+) -> anyhow::Result<credit::Postings> {
+    let dir = store_dir()?;
+
+    if r.sync {
+        pb.enable_steady_tick(120);
+        pb.set_message(&format!("Syncing {}/{}...", owner, repo));
+        let synced = credit::sync_repo(client, &dir, owner, repo);
+        pb.finish_and_clear();
+        synced?;
+    }
+
+    credit::stored_threads(client, &dir, &r.start, &r.end, owner, repo)
+}
+
+fn cache(cache: &credit::Cache, c: Cache) -> anyhow::Result<String> {
+    if c.clear || c.expired {
+        let removed = cache.clear(c.expired)?;
+        Ok(format!("Removed {} cached responses.", removed))
+    } else {
+        let stats = cache.stats();
+        Ok(format!(
+            "Location: {}\nEntries: {} ({} expired)\nSize: {:.1} MB",
+            cache.dir().display(),
+            stats.entries,
+            stats.expired,
+            stats.bytes as f64 / (1024.0 * 1024.0)
+        ))
+    }
+}
+
+fn limit(client: &credit::Client) -> anyhow::Result<String> {
+    let rl = credit::rate_limit(client)?;
     let json = serde_json::to_string(&rl)?;
 
     Ok(json)
 }
 
-fn repo(token: &str, r: &Repo) -> anyhow::Result<String> {
+fn estimate(client: &credit::Client, e: &Estimate) -> anyhow::Result<String> {
+    if e.repos.is_empty() {
+        return Err(anyhow!("No repositories given!"));
+    }
+
+    let est = credit::estimate(client, &e.repos, e.commits)?;
+
+    // The Markdown report carries its own warning.
+    if e.json && est.exceeds_quota() {
+        eprintln!(
+            "Warning: about {} API points are needed, but only {} remain.",
+            est.points(),
+            est.limit.remaining
+        );
+    }
+
+    if e.json {
+        let json = serde_json::to_string(&est)?;
+        Ok(json)
+    } else {
+        Ok(est.to_string())
+    }
+}
+
+fn repo(client: &credit::Client, r: &Repo) -> anyhow::Result<String> {
     if r.repos.is_empty() {
         Err(anyhow!("No repositories given!"))
     } else {
+        // The first Ctrl-C stops fetching, keeping what has been saved so far.
+        // A second one quits outright.
+        let c = client.clone();
+        ctrlc::set_handler(move || {
+            if c.is_interrupted() {
+                process::exit(130);
+            }
+            eprintln!("Interrupted! Stopping...");
+            c.interrupt();
+        })?;
+
         let m = MultiProgress::new();
 
         let spinners = r
@@ -210,29 +817,73 @@ fn repo(token: &str, r: &Repo) -> anyhow::Result<String> {
         // spinners to appear.
         thread::spawn(move || m.join_and_clear());
 
-        let (bads, goods): (Vec<_>, Vec<_>) = spinners
+        let results: Vec<_> = spinners
             .par_iter()
             .map(|(ipb, ppb, owner, repo)| {
-                credit::repo_threads(
-                    token, &ipb, &ppb, r.serial, r.commits, &r.start, &r.end, &owner, &repo,
-                )
+                let client = client.scoped();
+                let result = if r.local || r.sync {
+                    local_threads(&client, ipb, r, owner, repo)
+                } else {
+                    credit::repo_threads(
+                        &client, ipb, ppb, r.commits, &r.start, &r.end, owner, repo,
+                    )
+                };
+                (format!("{}/{}", owner, repo), client.cost(), result)
             })
-            .partition_map(From::from);
+            .collect();
+
+        let mut meta = credit::Meta::default();
+        let mut bads = vec![];
+        let mut goods = vec![];
+        for (name, cost, result) in results {
+            meta.costs.insert(name, cost);
+            match result {
+                Ok(ps) => goods.push(ps),
+                Err(e) => bads.push(e),
+            }
+        }
+        meta.total_cost = Some(client.total_cost());
+        meta.interrupted = client.is_interrupted();
+
+        if client.total_cost().requests > 0 {
+            eprintln!("{}", cost_summary(client, &meta));
+        }
 
         if !bads.is_empty() {
             eprintln!("There were some errors:");
-            for e in bads {
-                eprintln!("{}", e);
+            for e in bads.iter() {
+                eprintln!("{}", diagnose(e));
             }
         }
 
+        let resumable = !r.local && !r.sync && (meta.interrupted || !bads.is_empty());
+        if resumable {
+            eprintln!("Progress has been saved. Run again with --resume to continue.");
+        }
+
+        if meta.interrupted && !r.partial {
+            return Err(anyhow!(
+                "Interrupted. Pass --partial to report on what was fetched anyway."
+            ));
+        }
+
         if !goods.is_empty() {
             let zero = credit::Postings {
                 issues: vec![],
                 prs: vec![],
             };
-            let all = goods.into_iter().fold(zero, |acc, ps| acc.combine(ps));
-            let stats = all.statistics();
+            let all = goods
+                .into_iter()
+                .fold(zero, |acc, ps| acc.combine(ps))
+                .with_labels(&r.label, &r.exclude_label);
+            let labels = if r.by_label {
+                all.label_statistics()
+            } else {
+                BTreeMap::new()
+            };
+            let mut stats = all.statistics();
+            stats.labels = labels;
+            stats.meta = meta;
 
             if r.json {
                 let json = serde_json::to_string(&stats)?;
@@ -262,5 +913,64 @@ fn datetime(date: &str) -> anyhow::Result<DateTime<Utc>> {
         .and_hms_opt(0, 0, 0)
         .context("Failed to parse date.")?;
 
-    Ok(DateTime::from_utc(naive, Utc))
+    Ok(Utc.from_utc_datetime(&naive))
+}
+
+#[test]
+fn network_settings() {
+    let vars = |pairs: &'static [(&'static str, &'static str)]| {
+        move |v: &str| {
+            pairs
+                .iter()
+                .find(|(name, _)| *name == v)
+                .map(|(_, value)| value.to_string())
+        }
+    };
+    let config = |toml: &str| toml::from_str::<Config>(toml).unwrap();
+
+    // Nothing set anywhere.
+    let net = config("").network(&vars(&[])).unwrap();
+    assert_eq!(None, net.proxy);
+    assert_eq!(Some(Duration::from_secs(30)), net.connect_timeout);
+    assert_eq!(None, net.timeout);
+    assert_eq!(1, net.low_speed_limit);
+    assert_eq!(Duration::from_secs(60), net.low_speed_time);
+
+    // The standard proxy variables, when nothing else is set.
+    let standard = vars(&[
+        ("HTTPS_PROXY", "http://standard:3128"),
+        ("no_proxy", "localhost"),
+    ]);
+    let net = config("").network(&standard).unwrap();
+    assert_eq!(Some("http://standard:3128".to_string()), net.proxy);
+    assert_eq!(Some("localhost".to_string()), net.no_proxy);
+
+    // The config file beats the standard variables, and 0 disables a timeout.
+    let file = config(
+        "[network]\nproxy = \"http://file:3128\"\nconnect_timeout = 0\ntimeout = 300\nlow_speed_limit = 10",
+    );
+    let net = file.network(&standard).unwrap();
+    assert_eq!(Some("http://file:3128".to_string()), net.proxy);
+    assert_eq!(Some("localhost".to_string()), net.no_proxy);
+    assert_eq!(None, net.connect_timeout);
+    assert_eq!(Some(Duration::from_secs(300)), net.timeout);
+    assert_eq!(10, net.low_speed_limit);
+
+    // `CREDIT_*` variables beat everything, but empty ones are ignored.
+    let credit = vars(&[
+        ("CREDIT_PROXY", "http://credit:3128"),
+        ("CREDIT_NO_PROXY", ""),
+        ("CREDIT_CONNECT_TIMEOUT", "5"),
+        ("CREDIT_TIMEOUT", "0"),
+        ("NO_PROXY", "localhost"),
+    ]);
+    let net = file.network(&credit).unwrap();
+    assert_eq!(Some("http://credit:3128".to_string()), net.proxy);
+    assert_eq!(Some("localhost".to_string()), net.no_proxy);
+    assert_eq!(Some(Duration::from_secs(5)), net.connect_timeout);
+    assert_eq!(None, net.timeout);
+
+    assert!(config("")
+        .network(&vars(&[("CREDIT_TIMEOUT", "soon")]))
+        .is_err());
 }
@@ -1,28 +1,119 @@
 //! Types and functions for the `repo` command.
 
+use crate::checkpoint::{Checkpoint, Resumed};
+use crate::cost::{self, OverBudget};
 use crate::github;
-use chrono::{DateTime, Utc};
-use serde::Deserialize;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use indicatif::{ProgressBar, ProgressStyle};
+use itertools::Itertools;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
 
 /// A single structure that represents the results from either an `issues` call
 /// or a `pullRequests` call from the GraphQL API.
-#[derive(Deserialize)]
+///
+/// Fields marked `#[serde(default)]` are absent from threads saved by older
+/// versions.
+#[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Issue {
+    #[serde(default)]
+    pub id: String,
+    pub number: u32,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub url: String,
     pub author: Option<Author>,
     pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
     pub closed_at: Option<DateTime<Utc>>,
+    /// Only present for Issues.
+    #[serde(default)]
+    pub state_reason: Option<StateReason>,
     pub merged_at: Option<DateTime<Utc>>,
-    pub comments: github::Edges<Comment>,
-    pub commits: Option<CommitCount>,
+    pub comments: Connection<Comment>,
+    #[serde(default)]
+    pub labels: Option<Connection<Label>>,
+    #[serde(default)]
+    pub reaction_groups: Option<Vec<ReactionGroup>>,
+    /// Only present for Pull Requests.
+    #[serde(default)]
+    pub reviews: Option<Connection<Review>>,
+    pub commits: Option<Count>,
+}
+
+/// The comments, reviews, labels or reactors of a thread. Only the first page
+/// of them arrives with the thread itself.
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Connection<A> {
+    #[serde(default)]
+    pub total_count: usize,
+    /// Where the first page left off. Cleared once the rest have been fetched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_info: Option<github::PageInfo>,
+    pub edges: Vec<github::Node<A>>,
+    /// Did fetching every one take follow-up queries? Not a Github field.
+    #[serde(default)]
+    pub followed_up: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Author {
     pub login: String,
 }
 
-#[derive(Debug, Deserialize)]
+/// The reactions of one kind to a thread, and (the first 100 of) who left them.
+#[derive(Deserialize, Serialize)]
+pub struct ReactionGroup {
+    pub content: Reaction,
+    pub reactors: Connection<Author>,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Reaction {
+    ThumbsUp,
+    ThumbsDown,
+    Laugh,
+    Hooray,
+    Confused,
+    Heart,
+    Rocket,
+    Eyes,
+    /// Anything Github adds later.
+    #[serde(other)]
+    Unknown,
+}
+
+/// Why an Issue is in its current state.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum StateReason {
+    Completed,
+    NotPlanned,
+    Duplicate,
+    Reopened,
+    /// Anything Github adds later.
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Label {
+    pub name: String,
+}
+
+/// A comment on a thread.
+///
+/// Unlike threads, comments come without their reactions. Naming who reacted
+/// takes a `reactors` connection in each of the 8 reaction groups of every
+/// comment, which Github counts as 80,000 more requests for a page of 100
+/// threads: 800 points a page, instead of about 10.
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Comment {
     pub author: Option<Author>,
@@ -30,9 +121,48 @@ pub struct Comment {
     pub created_at: DateTime<Utc>,
 }
 
-#[derive(Deserialize)]
+/// A review of a Pull Request.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Review {
+    pub author: Option<Author>,
+    pub author_association: Association,
+    pub state: ReviewState,
+    /// Absent while the review is still pending.
+    pub submitted_at: Option<DateTime<Utc>>,
+    /// Absent from reviews saved by older versions.
+    #[serde(default)]
+    pub id: String,
+    /// How many line-by-line comments the review holds, once counted by a
+    /// follow-up query. Not a Github field.
+    #[serde(default)]
+    pub comment_count: usize,
+}
+
+impl Review {
+    /// Has the review been submitted, rather than still being drafted?
+    pub fn is_submitted(&self) -> bool {
+        self.state != ReviewState::Pending && self.submitted_at.is_some()
+    }
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ReviewState {
+    Pending,
+    Commented,
+    Approved,
+    ChangesRequested,
+    Dismissed,
+    /// Anything Github adds later.
+    #[serde(other)]
+    Unknown,
+}
+
+/// The size of a connection, when only that is needed.
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct CommitCount {
+pub struct Count {
     pub total_count: usize,
 }
 
@@ -62,6 +192,550 @@ impl Issues {
     }
 }
 
+const ISSUES_QUERY: &str = "
+query($owner: String!, $name: String!, $first: Int!, $after: String, $orderBy: IssueOrder) {
+  rateLimit {
+    cost
+    remaining
+    resetAt
+  }
+  repository(owner: $owner, name: $name) {
+    issues(first: $first, after: $after, orderBy: $orderBy) {
+      totalCount
+      pageInfo {
+        hasNextPage
+        endCursor
+      }
+      edges {
+        node {
+          id
+          number
+          title
+          url
+          author {
+            login
+          }
+          createdAt
+          updatedAt
+          closedAt
+          stateReason
+          reactionGroups {
+            content
+            reactors(first: 100) {
+              totalCount
+              edges {
+                node {
+                  ... on Actor {
+                    login
+                  }
+                }
+              }
+            }
+          }
+          labels(first: 100) {
+            edges {
+              node {
+                name
+              }
+            }
+          }
+          comments(first: 100) {
+            totalCount
+            pageInfo {
+              hasNextPage
+              endCursor
+            }
+            edges {
+              node {
+                author {
+                  login
+                }
+                authorAssociation
+                createdAt
+              }
+            }
+          }
+        }
+      }
+    }
+  }
+}";
+
+const PRS_QUERY: &str = "
+query($owner: String!, $name: String!, $first: Int!, $after: String, $orderBy: IssueOrder, $commits: Boolean!) {
+  rateLimit {
+    cost
+    remaining
+    resetAt
+  }
+  repository(owner: $owner, name: $name) {
+    pullRequests(first: $first, after: $after, orderBy: $orderBy) {
+      totalCount
+      pageInfo {
+        hasNextPage
+        endCursor
+      }
+      edges {
+        node {
+          id
+          number
+          title
+          url
+          author {
+            login
+          }
+          createdAt
+          updatedAt
+          closedAt
+          reactionGroups {
+            content
+            reactors(first: 100) {
+              totalCount
+              edges {
+                node {
+                  ... on Actor {
+                    login
+                  }
+                }
+              }
+            }
+          }
+          labels(first: 100) {
+            edges {
+              node {
+                name
+              }
+            }
+          }
+          mergedAt
+          commits @include(if: $commits) {
+            totalCount
+          }
+          reviews(first: 100) {
+            totalCount
+            pageInfo {
+              hasNextPage
+              endCursor
+            }
+            edges {
+              node {
+                author {
+                  login
+                }
+                authorAssociation
+                state
+                submittedAt
+                id
+              }
+            }
+          }
+          comments(first: 100) {
+            totalCount
+            pageInfo {
+              hasNextPage
+              endCursor
+            }
+            edges {
+              node {
+                author {
+                  login
+                }
+                authorAssociation
+                createdAt
+              }
+            }
+          }
+        }
+      }
+    }
+  }
+}";
+
+/// Threads created within some period, found via search. The same fields as the
+/// queries above are asked for.
+const SEARCH_QUERY: &str = "
+query($q: String!, $first: Int!, $after: String, $commits: Boolean!) {
+  rateLimit {
+    cost
+    remaining
+    resetAt
+  }
+  search(query: $q, type: ISSUE, first: $first, after: $after) {
+    issueCount
+    pageInfo {
+      hasNextPage
+      endCursor
+    }
+    edges {
+      node {
+        ... on Issue {
+          id
+          number
+          title
+          url
+          author {
+            login
+          }
+          createdAt
+          updatedAt
+          closedAt
+          stateReason
+          reactionGroups {
+            content
+            reactors(first: 100) {
+              totalCount
+              edges {
+                node {
+                  ... on Actor {
+                    login
+                  }
+                }
+              }
+            }
+          }
+          labels(first: 100) {
+            edges {
+              node {
+                name
+              }
+            }
+          }
+          comments(first: 100) {
+            totalCount
+            pageInfo {
+              hasNextPage
+              endCursor
+            }
+            edges {
+              node {
+                author {
+                  login
+                }
+                authorAssociation
+                createdAt
+              }
+            }
+          }
+        }
+        ... on PullRequest {
+          id
+          number
+          title
+          url
+          author {
+            login
+          }
+          createdAt
+          updatedAt
+          closedAt
+          reactionGroups {
+            content
+            reactors(first: 100) {
+              totalCount
+              edges {
+                node {
+                  ... on Actor {
+                    login
+                  }
+                }
+              }
+            }
+          }
+          labels(first: 100) {
+            edges {
+              node {
+                name
+              }
+            }
+          }
+          mergedAt
+          commits @include(if: $commits) {
+            totalCount
+          }
+          reviews(first: 100) {
+            totalCount
+            pageInfo {
+              hasNextPage
+              endCursor
+            }
+            edges {
+              node {
+                author {
+                  login
+                }
+                authorAssociation
+                state
+                submittedAt
+                id
+              }
+            }
+          }
+          comments(first: 100) {
+            totalCount
+            pageInfo {
+              hasNextPage
+              endCursor
+            }
+            edges {
+              node {
+                author {
+                  login
+                }
+                authorAssociation
+                createdAt
+              }
+            }
+          }
+        }
+      }
+    }
+  }
+}";
+
+/// How many Issues and PRs a repository has, and when the oldest and newest of
+/// each were opened. Cheap enough to ask before deciding how to page through
+/// them.
+const SIZE_QUERY: &str = "
+query($owner: String!, $name: String!) {
+  rateLimit {
+    cost
+    remaining
+    resetAt
+  }
+  repository(owner: $owner, name: $name) {
+    issues(first: 1) {
+      totalCount
+      edges {
+        node {
+          createdAt
+        }
+      }
+    }
+    newestIssue: issues(last: 1) {
+      edges {
+        node {
+          createdAt
+        }
+      }
+    }
+    pullRequests(first: 1) {
+      totalCount
+      edges {
+        node {
+          createdAt
+        }
+      }
+    }
+    newestPullRequest: pullRequests(last: 1) {
+      edges {
+        node {
+          createdAt
+        }
+      }
+    }
+  }
+}";
+
+/// How many line-by-line comments each of some reviews holds. Asked for apart
+/// from the pages of PRs, where nesting them under every review would make
+/// each page around 10 times more expensive.
+const REVIEW_COMMENTS_QUERY: &str = "
+query($ids: [ID!]!) {
+  rateLimit {
+    cost
+    remaining
+    resetAt
+  }
+  nodes(ids: $ids) {
+    ... on PullRequestReview {
+      id
+      comments {
+        totalCount
+      }
+    }
+  }
+}";
+
+/// The comments of a thread past its first page.
+const COMMENTS_QUERY: &str = "
+query($id: ID!, $first: Int!, $after: String) {
+  rateLimit {
+    cost
+    remaining
+    resetAt
+  }
+  node(id: $id) {
+    ... on Issue {
+      comments(first: $first, after: $after) {
+        pageInfo {
+          hasNextPage
+          endCursor
+        }
+        edges {
+          node {
+            author {
+              login
+            }
+            authorAssociation
+            createdAt
+          }
+        }
+      }
+    }
+    ... on PullRequest {
+      comments(first: $first, after: $after) {
+        pageInfo {
+          hasNextPage
+          endCursor
+        }
+        edges {
+          node {
+            author {
+              login
+            }
+            authorAssociation
+            createdAt
+          }
+        }
+      }
+    }
+  }
+}";
+
+/// The reviews of a Pull Request past its first page.
+const REVIEWS_QUERY: &str = "
+query($id: ID!, $first: Int!, $after: String) {
+  rateLimit {
+    cost
+    remaining
+    resetAt
+  }
+  node(id: $id) {
+    ... on PullRequest {
+      reviews(first: $first, after: $after) {
+        pageInfo {
+          hasNextPage
+          endCursor
+        }
+        edges {
+          node {
+            author {
+              login
+            }
+            authorAssociation
+            state
+            submittedAt
+            id
+          }
+        }
+      }
+    }
+  }
+}";
+
+#[derive(Deserialize)]
+struct CommentsNode {
+    node: Commented,
+}
+
+#[derive(Deserialize)]
+struct Commented {
+    comments: github::Paged<Comment>,
+}
+
+#[derive(Deserialize)]
+struct ReviewsNode {
+    node: Reviewed,
+}
+
+#[derive(Deserialize)]
+struct Reviewed {
+    reviews: github::Paged<Review>,
+}
+
+/// How many threads of one kind a repository has, and one of them.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Size {
+    /// Only asked for once per kind.
+    #[serde(default)]
+    pub(crate) total_count: usize,
+    edges: Vec<github::Node<Opened>>,
+}
+
+impl Size {
+    /// When the thread that came along was opened.
+    fn opened(&self) -> Option<DateTime<Utc>> {
+        self.edges.first().map(|n| n.node.created_at)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Opened {
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Sizes {
+    /// Along with the oldest Issue.
+    pub(crate) issues: Size,
+    newest_issue: Size,
+    /// Along with the oldest PR.
+    pub(crate) pull_requests: Size,
+    newest_pull_request: Size,
+}
+
+impl Sizes {
+    /// All threads of the given kind along with the oldest, then the newest.
+    fn of(&self, mode: &Mode) -> (&Size, &Size) {
+        match mode {
+            Mode::Issues => (&self.issues, &self.newest_issue),
+            _ => (&self.pull_requests, &self.newest_pull_request),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SizeQuery {
+    repository: Sizes,
+}
+
+#[derive(Deserialize)]
+struct ReviewComments {
+    id: String,
+    comments: Count,
+}
+
+#[derive(Deserialize)]
+struct ReviewCommentsQuery {
+    /// Null for any review that has since been deleted.
+    nodes: Vec<Option<ReviewComments>>,
+}
+
+#[derive(Deserialize)]
+struct SearchResult {
+    search: github::Paged<Issue>,
+}
+
+/// The most threads Github will give in a single page, and how many are asked
+/// for until it times out.
+pub(crate) const PAGE_SIZE: u32 = 100;
+
+/// Repositories with more threads of some kind than this are fetched in
+/// parallel, in shards of creation dates.
+const SHARD_ABOVE: usize = 2000;
+
+/// Roughly how many threads each shard should hold, assuming they were opened
+/// at an even pace.
+const SHARD_SIZE: usize = 500;
+
+/// Github's search yields no more than this many results for a single query,
+/// so shards any larger are split further.
+const SEARCH_LIMIT: usize = 1000;
+
+#[derive(Clone, Copy)]
 pub enum Mode {
     Issues,
     PRs,
@@ -69,117 +743,526 @@ pub enum Mode {
 }
 
 impl Mode {
-    fn graph_call(&self) -> &str {
+    fn query(&self) -> &'static str {
+        match self {
+            Mode::Issues => ISSUES_QUERY,
+            _ => PRS_QUERY,
+        }
+    }
+
+    fn commits(&self) -> bool {
+        matches!(self, Mode::PRsWithCommits)
+    }
+
+    /// A name for this kind of crawl, for its checkpoint.
+    fn kind(&self) -> &'static str {
         match self {
             Mode::Issues => "issues",
-            _ => "pullRequests",
+            Mode::PRs => "prs",
+            Mode::PRsWithCommits => "prs-commits",
         }
     }
 
-    fn merged_field(&self) -> &str {
+    /// How many requests Github's cost formula counts a page of `size` threads
+    /// as.
+    pub(crate) fn page_requests(&self, size: u32) -> u32 {
+        cost::requests(self.query(), size)
+    }
+
+    /// The search qualifier for this kind of thread.
+    fn qualifier(&self) -> &'static str {
         match self {
-            Mode::Issues => "",
-            _ => "mergedAt",
+            Mode::Issues => "is:issue",
+            _ => "is:pr",
         }
     }
+}
+
+/// The order in which threads are paged through.
+#[derive(Clone, Copy)]
+pub enum Order {
+    /// Oldest first, which is Github's default.
+    Created,
+    /// Most recently updated first.
+    Updated,
+}
 
-    fn commits(&self) -> &str {
+impl Order {
+    fn order_by(&self) -> serde_json::Value {
         match self {
-            Mode::PRsWithCommits => "commits { totalCount }",
-            _ => "",
-        }
-    }
-}
-
-fn issue_query(mode: &Mode, owner: &str, repo: &str, page: Option<&str>) -> String {
-    format!(
-        "{{ \
-    \"query\": \"{{ \
-        repository(owner: \\\"{}\\\", name: \\\"{}\\\") {{ \
-            {}(first: 100{}) {{ \
-                pageInfo {{ \
-                    hasNextPage \
-                    endCursor \
-                }} \
-                edges {{ \
-                    node {{ \
-                        author {{ \
-                            login \
-                        }} \
-                        createdAt \
-                        closedAt \
-                        {} \
-                        {} \
-                        comments(first: 100) {{ \
-                            edges {{ \
-                                node {{ \
-                                    author {{ \
-                                        login \
-                                    }} \
-                                    authorAssociation \
-                                    createdAt \
-                                }} \
-                            }} \
-                        }} \
-                    }} \
-                }} \
-            }} \
-        }} \
-    }}\" \
-    }}",
-        owner,
-        repo,
-        mode.graph_call(),
-        page.map(|p| format!(", after: \\\"{}\\\"", p))
-            .unwrap_or_else(|| "".to_string()),
-        mode.merged_field(),
-        mode.commits(),
+            Order::Created => serde_json::Value::Null,
+            Order::Updated => json!({ "field": "UPDATED_AT", "direction": "DESC" }),
+        }
+    }
+}
+
+/// Roughly how many full pages it takes to fetch this many threads.
+pub(crate) fn pages_needed(total: usize) -> usize {
+    let pages = total.div_ceil(PAGE_SIZE as usize).max(1);
+
+    // Each shard usually ends on a partial page.
+    if total > SHARD_ABOVE {
+        pages + total.div_ceil(SHARD_SIZE)
+    } else {
+        pages
+    }
+}
+
+fn issue_query(
+    mode: &Mode,
+    order: &Order,
+    owner: &str,
+    repo: &str,
+    page: Option<&str>,
+    first: u32,
+) -> github::Request {
+    github::Request::new(
+        mode.query(),
+        json!({
+            "owner": owner,
+            "name": repo,
+            "first": first,
+            "after": page,
+            "orderBy": order.order_by(),
+            "commits": mode.commits(),
+        }),
+    )
+}
+
+/// How progress bars look once the number of threads to fetch is known.
+const BAR_TEMPLATE: &str = "{spinner} {prefix} [{bar:25}] {pos}/{len}, {eta} left, {msg}";
+
+/// Where threads are reported as they're fetched: to a progress bar, and to
+/// the crawl's checkpoint, if there is one.
+#[derive(Clone, Copy)]
+struct Progress<'a> {
+    client: &'a github::Client,
+    bar: &'a ProgressBar,
+    checkpoint: Option<&'a Checkpoint>,
+}
+
+impl Progress<'_> {
+    /// Now that it's known how many threads there are, show how far along the
+    /// crawl is.
+    fn expect(&self, total: usize) {
+        let style = ProgressStyle::default_bar()
+            .template(BAR_TEMPLATE)
+            .progress_chars("=> ");
+        self.bar.set_style(style);
+        self.bar.set_length(total as u64);
+        self.bar.reset_eta();
+    }
+
+    /// Count some freshly fetched threads, and what they cost.
+    fn fetched(&self, threads: usize) {
+        self.bar.inc(threads as u64);
+        self.bar
+            .set_message(&format!("{} API points", self.client.cost().points));
+    }
+}
+
+/// The Issues or Pull Requests of a project, depending on the `Mode` given, a
+/// page at a time.
+pub fn threads<'a>(
+    client: &'a github::Client,
+    mode: Mode,
+    order: Order,
+    owner: &'a str,
+    repo: &'a str,
+) -> github::Pages<'a, Issue> {
+    github::Pages::new(
+        client,
+        &format!("{}/{}/{}", owner, repo, mode.kind()),
+        (PAGE_SIZE, PAGE_SIZE),
+        move |cursor, first| issue_query(&mode, &order, owner, repo, cursor, first),
+        |result: IssueRepo| result.repository.page(),
     )
 }
 
 /// Fetch all Issues or Pull Requests for a project, depending on the `Mode` given.
+///
+/// Large projects are fetched in parallel shards of creation dates, while the
+/// rest are simply paged through. Either way, `bar` is kept up to date with
+/// how many threads have been fetched, and how many there are.
 pub fn issues(
-    token: &str,
+    client: &github::Client,
     end: &Option<DateTime<Utc>>,
     mode: &Mode,
     owner: &str,
     repo: &str,
+    bar: &ProgressBar,
 ) -> anyhow::Result<Vec<Issue>> {
-    issues_work(token, end, mode, owner, repo, None)
+    // If the user supplied `--end`, we don't need to page past the point
+    // they're looking for.
+    let stop = |i: &Issue| end.map(|e| i.created_at > e).unwrap_or(false);
+    let (checkpoint, resumed) = match client.checkpoint(owner, repo, mode.kind(), end)? {
+        Some((c, r)) => (Some(c), r),
+        None => (None, Resumed::default()),
+    };
+    let progress = Progress {
+        client,
+        bar,
+        checkpoint: checkpoint.as_ref(),
+    };
+
+    let issues = match resumed.plan {
+        Some((from, until, shards)) => {
+            // Near enough, as shards were sized from the total.
+            progress.expect(shards * SHARD_SIZE);
+            sharded(mode, owner, repo, from, until, shards, progress)?
+        }
+        None if !resumed.threads.is_empty() => {
+            let mut issues = resumed.threads;
+            progress.fetched(issues.len());
+            let done = issues.last().map(stop).unwrap_or(false);
+            if let Some(cursor) = resumed.cursor.filter(|_| !done) {
+                let pages = threads(client, *mode, Order::Created, owner, repo)
+                    .resume(cursor)
+                    .inspect(|page| {
+                        if let Some(total) = page.as_ref().ok().and_then(|p| p.total_count) {
+                            progress.expect(total);
+                        }
+                    });
+                issues.append(&mut collect_until(pages, &stop, progress)?);
+            }
+            issues
+        }
+        None => fresh(end, mode, owner, repo, &stop, progress)?,
+    };
+
+    if let Some(c) = checkpoint.filter(|_| !client.stopped_early()) {
+        c.finish()?;
+    }
+
+    Ok(issues)
 }
 
-fn issues_work(
-    token: &str,
+/// How many Issues and PRs a repository has.
+pub(crate) fn sizes(client: &github::Client, owner: &str, repo: &str) -> anyhow::Result<Sizes> {
+    let request = github::Request::new(SIZE_QUERY, json!({ "owner": owner, "name": repo }));
+    let result: SizeQuery = github::lookup(client, &request)?;
+    Ok(result.repository)
+}
+
+/// Fetch all threads of some kind, without any earlier progress to go on.
+fn fresh(
     end: &Option<DateTime<Utc>>,
     mode: &Mode,
     owner: &str,
     repo: &str,
-    page: Option<&str>,
+    stop: &dyn Fn(&Issue) -> bool,
+    progress: Progress,
+) -> anyhow::Result<Vec<Issue>> {
+    let sizes = match sizes(progress.client, owner, repo) {
+        // Nothing can be fetched anyway, so stop as paging would.
+        Err(e) if e.is::<OverBudget>() || e.is::<github::Interrupted>() => return Ok(vec![]),
+        sizes => sizes?,
+    };
+    let (all, newest) = sizes.of(mode);
+    progress.expect(all.total_count);
+
+    match (all.opened(), newest.opened()) {
+        (Some(oldest), Some(newest)) if all.total_count > SHARD_ABOVE => {
+            // Not the current time, so that a replayed run searches exactly
+            // the shards its recording did.
+            let until = end.unwrap_or(newest);
+            let shards = all.total_count.div_ceil(SHARD_SIZE);
+            sharded(mode, owner, repo, oldest, until, shards, progress)
+        }
+        _ => {
+            let pages = threads(progress.client, *mode, Order::Created, owner, repo);
+            collect_until(pages, stop, progress)
+        }
+    }
+}
+
+/// Fetch the Issues or Pull Requests of a project that have been updated since
+/// the given time, most recent first.
+pub fn updated_since(
+    client: &github::Client,
+    since: &Option<DateTime<Utc>>,
+    mode: &Mode,
+    owner: &str,
+    repo: &str,
 ) -> anyhow::Result<Vec<Issue>> {
-    let body = issue_query(mode, owner, repo, page);
-    let issue_query: IssueRepo = github::lookup(token, body)?;
+    let stale = |i: &Issue| since.map(|s| i.updated_at < s).unwrap_or(false);
+    let pages = threads(client, *mode, Order::Updated, owner, repo);
+    let progress = Progress {
+        client,
+        bar: &ProgressBar::hidden(),
+        checkpoint: None,
+    };
+    let issues = collect_until(pages, &stale, progress)?;
+    Ok(issues.into_iter().filter(|i| !stale(i)).collect())
+}
 
-    let page = issue_query.repository.page();
-    let info = page.page_info;
-    let mut issues: Vec<Issue> = page.edges.into_iter().map(|n| n.node).collect();
+/// Gather threads page by page, until there are no more, or until the last
+/// thread of a page satisfies `stop`. Each page is counted, and saved to the
+/// checkpoint if there is one.
+fn collect_until<I>(
+    pages: I,
+    stop: &dyn Fn(&Issue) -> bool,
+    progress: Progress,
+) -> anyhow::Result<Vec<Issue>>
+where
+    I: Iterator<Item = anyhow::Result<github::Paged<Issue>>>,
+{
+    let mut issues = Vec::new();
 
-    // If the user supplied `--end`, we don't need to page past the point
-    // they're looking for.
-    let stop_early = end
-        .and_then(|e| issues.last().map(|i| i.created_at > e))
-        .unwrap_or(false);
-
-    match info.end_cursor {
-        Some(c) if info.has_next_page && !stop_early => {
-            let mut next = issues_work(token, end, mode, owner, repo, Some(&c))?;
-            issues.append(&mut next);
+    for page in pages {
+        let page = page?;
+        let info = page.page_info;
+        let mut threads: Vec<Issue> = page.edges.into_iter().map(|n| n.node).collect();
+        let stop_early = threads.last().map(stop).unwrap_or(false);
+
+        for thread in threads.iter_mut() {
+            follow_up(progress.client, thread)?;
+        }
+        review_comments(progress.client, &mut threads)?;
+
+        // Threads whose follow-ups were cut short shouldn't be saved as if
+        // they were complete, so this page is fetched again on resumption.
+        if let Some(cp) = progress
+            .checkpoint
+            .filter(|_| !progress.client.stopped_early())
+        {
+            let next = info.end_cursor.as_deref().filter(|_| info.has_next_page);
+            cp.page(next, &threads)?;
+        }
+        progress.fetched(threads.len());
+
+        issues.append(&mut threads);
+
+        if stop_early {
+            break;
+        }
+    }
+
+    Ok(issues)
+}
+
+/// Fetch the comments and reviews of a thread that didn't fit on its first
+/// page, if any.
+fn follow_up(client: &github::Client, thread: &mut Issue) -> anyhow::Result<()> {
+    let id = thread.id.as_str();
+    rest_of(
+        client,
+        "comments",
+        id,
+        &mut thread.comments,
+        COMMENTS_QUERY,
+        |r: CommentsNode| r.node.comments,
+    )?;
+
+    if let Some(reviews) = thread.reviews.as_mut() {
+        rest_of(
+            client,
+            "reviews",
+            id,
+            reviews,
+            REVIEWS_QUERY,
+            |r: ReviewsNode| r.node.reviews,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Page through the rest of a connection of the node with the given ID, if it
+/// has more than its first page.
+/// Count the line-by-line comments of the submitted reviews among `threads`, a
+/// page of reviews at a time.
+fn review_comments(client: &github::Client, threads: &mut [Issue]) -> anyhow::Result<()> {
+    let mut reviews: HashMap<String, &mut Review> = threads
+        .iter_mut()
+        .flat_map(|t| t.reviews.iter_mut())
+        .flat_map(|c| c.edges.iter_mut().map(|n| &mut n.node))
+        .filter(|r| r.is_submitted() && !r.id.is_empty())
+        .map(|r| (r.id.clone(), r))
+        .collect();
+    let ids: Vec<String> = reviews.keys().cloned().sorted().collect();
+
+    for chunk in ids.chunks(PAGE_SIZE as usize) {
+        let request = github::Request::new(REVIEW_COMMENTS_QUERY, json!({ "ids": chunk }));
+        let result: ReviewCommentsQuery = match github::lookup(client, &request) {
+            // Paging stops quietly in these cases, so these counts do too.
+            Err(e) if e.is::<OverBudget>() || e.is::<github::Interrupted>() => return Ok(()),
+            result => result?,
+        };
+        for counted in result.nodes.into_iter().flatten() {
+            if let Some(review) = reviews.get_mut(&counted.id) {
+                review.comment_count = counted.comments.total_count;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn rest_of<A, R>(
+    client: &github::Client,
+    key: &str,
+    id: &str,
+    connection: &mut Connection<A>,
+    query: &'static str,
+    extract: fn(R) -> github::Paged<A>,
+) -> anyhow::Result<()>
+where
+    R: serde::de::DeserializeOwned,
+{
+    let cursor = match connection.page_info.take() {
+        Some(github::PageInfo {
+            has_next_page: true,
+            end_cursor: Some(cursor),
+        }) => cursor,
+        _ => return Ok(()),
+    };
+
+    let pages = github::Pages::new(
+        client,
+        key,
+        (PAGE_SIZE, PAGE_SIZE),
+        move |cursor, first| {
+            github::Request::new(query, json!({ "id": id, "first": first, "after": cursor }))
+        },
+        extract,
+    )
+    .resume(cursor);
+
+    // Paging ends quietly when the budget runs out or the run is interrupted,
+    // so only a last page proves that everything arrived.
+    let mut finished = false;
+    for page in pages {
+        let page = page?;
+        finished = !page.page_info.has_next_page;
+        connection.edges.extend(page.edges);
+    }
+    connection.followed_up = finished;
+    Ok(())
+}
+
+/// Split the period from `from` to `until` into evenly sized shards, and fetch
+/// the threads created within each in parallel, oldest first.
+fn sharded(
+    mode: &Mode,
+    owner: &str,
+    repo: &str,
+    from: DateTime<Utc>,
+    until: DateTime<Utc>,
+    shards: usize,
+    progress: Progress,
+) -> anyhow::Result<Vec<Issue>> {
+    if let Some(cp) = progress.checkpoint {
+        cp.plan(from, until, shards)?;
+    }
+
+    // Searches are inclusive of both ends, but shards exclude their last
+    // second, so the final one is nudged past `until`.
+    let until = until + Duration::seconds(1);
+    let span = (until - from) / shards.max(1) as i32;
+    let bounds: Vec<DateTime<Utc>> = (0..shards)
+        .map(|i| from + span * i as i32)
+        .chain(std::iter::once(until))
+        .collect();
+
+    let results: Vec<anyhow::Result<Vec<Issue>>> = bounds
+        .par_windows(2)
+        .map(|w| shard(mode, owner, repo, w[0], w[1], progress))
+        .collect();
+
+    let mut issues: Vec<Issue> = results.into_iter().flatten_ok().collect::<Result<_, _>>()?;
+    issues.sort_by_key(|i| (i.created_at, i.number));
+    issues.dedup_by_key(|i| i.number);
+    Ok(issues)
+}
+
+/// The threads created from `from` up to (but excluding) `until`. Shards with
+/// more threads than a search can yield are halved until they fit.
+fn shard(
+    mode: &Mode,
+    owner: &str,
+    repo: &str,
+    from: DateTime<Utc>,
+    until: DateTime<Utc>,
+    progress: Progress,
+) -> anyhow::Result<Vec<Issue>> {
+    let client = progress.client;
+    let cp = progress.checkpoint;
+    if let Some(done) = cp.and_then(|c| c.finished(from, until)) {
+        progress.fetched(done.len());
+        return Ok(done);
+    }
+
+    let last = until - Duration::seconds(1);
+    let query = format!(
+        "repo:{}/{} {} created:{}..{} sort:created-asc",
+        owner,
+        repo,
+        mode.qualifier(),
+        from.to_rfc3339_opts(SecondsFormat::Secs, false),
+        last.to_rfc3339_opts(SecondsFormat::Secs, false),
+    );
+
+    let mut pages = search(client, *mode, owner, repo, &query);
+    let first = match pages.next() {
+        None => return Ok(vec![]),
+        Some(first) => first?,
+    };
+
+    match first.total_count {
+        Some(count) if count > SEARCH_LIMIT && last > from => {
+            let mid = from + (until - from) / 2;
+            let (older, newer) = rayon::join(
+                || shard(mode, owner, repo, from, mid, progress),
+                || shard(mode, owner, repo, mid, until, progress),
+            );
+            let mut issues = older?;
+            issues.append(&mut newer?);
+            Ok(issues)
+        }
+        _ => {
+            let pages = std::iter::once(Ok(first)).chain(pages);
+            let unsaved = Progress {
+                checkpoint: None,
+                ..progress
+            };
+            let issues = collect_until(pages, &|_| false, unsaved)?;
+            // Only whole shards are saved, so one cut short is fetched again
+            // on resumption.
+            if let Some(cp) = cp.filter(|_| !client.stopped_early()) {
+                cp.shard(from, until, &issues)?;
+            }
             Ok(issues)
         }
-        _ => Ok(issues),
     }
 }
 
-#[derive(Debug, Deserialize)]
+/// The threads matching some search, a page at a time.
+///
+/// These share their page size with plain paging through the same threads, as
+/// they're just as heavy.
+fn search<'a>(
+    client: &'a github::Client,
+    mode: Mode,
+    owner: &str,
+    repo: &str,
+    query: &'a str,
+) -> github::Pages<'a, Issue> {
+    github::Pages::new(
+        client,
+        &format!("{}/{}/{}", owner, repo, mode.kind()),
+        (PAGE_SIZE, PAGE_SIZE),
+        move |cursor, first| {
+            github::Request::new(
+                SEARCH_QUERY,
+                json!({ "q": query, "first": first, "after": cursor, "commits": mode.commits() }),
+            )
+        },
+        |result: SearchResult| result.search,
+    )
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum Association {
     Owner,
@@ -192,18 +1275,356 @@ pub enum Association {
 
 impl Association {
     pub fn is_official(&self) -> bool {
-        match self {
-            Association::Owner => true,
-            Association::Member => true,
-            Association::Collaborator => true,
-            _ => false,
-        }
+        matches!(
+            self,
+            Association::Owner | Association::Member | Association::Collaborator
+        )
     }
 
     pub fn is_author(&self) -> bool {
-        match self {
-            Association::Author => true,
-            _ => false,
-        }
+        matches!(self, Association::Author)
     }
 }
+
+#[test]
+fn budget_stops_paging() {
+    use crate::fixtures;
+    use crate::transport::Fixtures;
+
+    let page = fixtures::costly_page(3, "issues", Some("next"), &[]);
+    let fixtures = Fixtures::new()
+        .respond("issues(first: 1)", fixtures::sizes(9, 0))
+        .respond("issues(", page.clone())
+        .respond("issues(", page.clone())
+        .respond("issues(", page);
+    let client = github::Client::github("fake")
+        .with_transport(fixtures)
+        .with_budget(7)
+        .scoped();
+
+    issues(
+        &client,
+        &None,
+        &Mode::Issues,
+        "a",
+        "b",
+        &ProgressBar::hidden(),
+    )
+    .unwrap();
+    let cost = client.cost();
+
+    assert_eq!(6, cost.points);
+    assert_eq!(2, cost.requests);
+    assert_eq!(Some(4990), cost.remaining);
+    assert!(cost.over_budget);
+    assert!(client.total_cost().over_budget);
+}
+
+#[test]
+fn sharded_paging() {
+    use crate::fixtures;
+    use crate::transport::Fixtures;
+    use serde_json::{json, Value};
+
+    let thread = |n: u32, day: &str| {
+        let date = format!("2020-01-{}T00:00:00Z", day);
+        fixtures::thread(n, &date, &date)
+    };
+    let search = |count: usize, threads: &[Value]| {
+        json!({"data": {"search": {"issueCount": count,
+          "pageInfo": {"hasNextPage": false, "endCursor": null},
+          "edges": threads}}})
+        .to_string()
+    };
+    // Five shards of a day each, the first of which is too big for a single
+    // search and so is split in two.
+    let fixtures = Fixtures::new()
+        .respond("issues(first: 1)", fixtures::sizes(2001, 0))
+        .respond(
+            "2020-01-01T00:00:00+00:00..2020-01-01T23:59:59+00:00",
+            search(1001, &[]),
+        )
+        .respond(
+            "2020-01-01T00:00:00+00:00..2020-01-01T11:59:59+00:00",
+            search(1, &[thread(1, "01")]),
+        )
+        .respond(
+            "2020-01-01T12:00:00+00:00..2020-01-01T23:59:59+00:00",
+            search(0, &[]),
+        )
+        .respond("created:2020-01-02", search(1, &[thread(2, "02")]))
+        .respond("created:2020-01-03", search(0, &[]))
+        .respond("created:2020-01-04", search(1, &[thread(3, "04")]))
+        .respond(
+            "2020-01-05T00:00:00+00:00..2020-01-06T00:00:00+00:00",
+            search(2, &[thread(4, "05"), thread(5, "06")]),
+        );
+    let client = github::Client::github("fake").with_transport(fixtures);
+    let end = "2020-01-06T00:00:00Z".parse().ok();
+
+    let bar = ProgressBar::hidden();
+
+    let numbers: Vec<u32> = issues(&client, &end, &Mode::Issues, "a", "b", &bar)
+        .unwrap()
+        .iter()
+        .map(|i| i.number)
+        .collect();
+    assert_eq!(vec![1, 2, 3, 4, 5], numbers);
+    assert_eq!(2001, bar.length());
+    assert_eq!(5, bar.position());
+}
+
+#[test]
+fn sharded_replay() {
+    use crate::fixtures;
+    use crate::transport::{Fixtures, Recorder, Replay};
+    use serde_json::json;
+
+    let search = |n: u32| {
+        let created = "2020-01-03T00:00:00Z";
+        json!({"data": {"search": {"issueCount": 1,
+          "pageInfo": {"hasNextPage": false, "endCursor": null},
+          "edges": [fixtures::thread(n, created, created)]}}})
+        .to_string()
+    };
+    let dir = std::env::temp_dir().join(format!("credit-replay-test-{}", std::process::id()));
+    let fixtures = (1..=5).fold(
+        Fixtures::new().respond("issues(first: 1)", fixtures::sizes(2001, 0)),
+        |f, n| f.respond("created:", search(n)),
+    );
+    let fetch = |client: &github::Client| -> Vec<u32> {
+        issues(
+            client,
+            &None,
+            &Mode::Issues,
+            "a",
+            "b",
+            &ProgressBar::hidden(),
+        )
+        .unwrap()
+        .iter()
+        .map(|i| i.number)
+        .collect()
+    };
+
+    // Without `--end`, the shards still have to be the same on replay.
+    let recorder = Recorder::new(dir.clone(), fixtures).unwrap();
+    let recorded = fetch(&github::Client::github("fake").with_transport(recorder));
+    // Bounds are to the second, so a second later is enough to tell.
+    std::thread::sleep(std::time::Duration::from_secs(1));
+    let replayed = fetch(&github::Client::github("fake").with_transport(Replay::new(dir.clone())));
+    assert_eq!(5, recorded.len());
+    assert_eq!(recorded, replayed);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn lazy_pages() {
+    use crate::fixtures;
+    use crate::transport::Fixtures;
+
+    let page = |next: Option<&str>| {
+        let created = "2020-01-01T00:00:00Z";
+        fixtures::costly_page(1, "issues", next, &[fixtures::thread(1, created, created)])
+    };
+    let fixtures = Fixtures::new()
+        .respond("issues(", page(Some("a")))
+        .respond("\"after\":\"a\"", page(Some("b")))
+        .respond("\"after\":\"b\"", page(None));
+    let client = github::Client::github("fake").with_transport(fixtures);
+
+    let mut items = threads(&client, Mode::Issues, Order::Created, "a", "b").items();
+    assert_eq!(1, items.next().unwrap().unwrap().number);
+    assert_eq!(1, client.total_cost().requests);
+
+    assert_eq!(2, items.count());
+    assert_eq!(3, client.total_cost().requests);
+}
+
+#[test]
+fn adaptive_page_size() {
+    use crate::fixtures;
+    use crate::transport::{Fixtures, Response};
+
+    let page = |next: Option<&str>| fixtures::page("issues", next, &[]);
+    let timeout = r#"{"data": null, "errors": [{"message": "Something went wrong while executing your query. This may be the result of a timeout."}]}"#;
+    let fixtures = Fixtures::new()
+        .respond_with(
+            "\"first\":100",
+            Response {
+                code: 502,
+                headers: vec![],
+                body: vec![],
+            },
+        )
+        .respond("\"first\":50", timeout)
+        .respond("\"first\":25", page(Some("a")))
+        .respond("\"first\":25", page(None))
+        .respond("\"first\":25", page(None));
+    let client = github::Client::github("fake").with_transport(fixtures);
+
+    let pages: Vec<_> = threads(&client, Mode::Issues, Order::Created, "a", "b")
+        .collect::<anyhow::Result<_>>()
+        .unwrap();
+    assert_eq!(2, pages.len());
+
+    // The smaller size is remembered for next time.
+    let pages: Vec<_> = threads(&client, Mode::Issues, Order::Created, "a", "b")
+        .collect::<anyhow::Result<_>>()
+        .unwrap();
+    assert_eq!(1, pages.len());
+}
+
+#[test]
+fn comment_follow_ups() {
+    use crate::fixtures;
+    use crate::transport::Fixtures;
+    use serde_json::json;
+
+    let comment = |login: &str| {
+        json!({"node": {"author": {"login": login}, "authorAssociation": "NONE",
+          "createdAt": "2020-01-02T00:00:00Z"}})
+    };
+    let mut thread = fixtures::thread(1, "2020-01-01T00:00:00Z", "2020-01-01T00:00:00Z");
+    thread["node"]["id"] = "I_1".into();
+    thread["node"]["comments"] = json!({"totalCount": 3,
+      "pageInfo": {"hasNextPage": true, "endCursor": "c1"},
+      "edges": [comment("alice")]});
+    let page = |points: u32| fixtures::costly_page(points, "issues", None, &[thread.clone()]);
+    let rest = |next: Option<&str>, login: &str| {
+        json!({"data": {"node": {"comments": {
+          "pageInfo": {"hasNextPage": next.is_some(), "endCursor": next},
+          "edges": [comment(login)]
+        }}}})
+        .to_string()
+    };
+    let fixtures = Fixtures::new()
+        .respond("issues(first: 1)", fixtures::sizes(1, 0))
+        .respond("issues(", page(1))
+        .respond("\"after\":\"c1\"", rest(Some("c2"), "bob"))
+        .respond("\"after\":\"c2\"", rest(None, "carol"));
+    let client = github::Client::github("fake").with_transport(fixtures);
+
+    let threads = issues(
+        &client,
+        &None,
+        &Mode::Issues,
+        "a",
+        "b",
+        &ProgressBar::hidden(),
+    )
+    .unwrap();
+    let logins: Vec<&str> = threads[0]
+        .comments
+        .edges
+        .iter()
+        .filter_map(|n| n.node.author.as_ref())
+        .map(|a| a.login.as_str())
+        .collect();
+    assert_eq!(vec!["alice", "bob", "carol"], logins);
+    assert!(threads[0].comments.followed_up);
+
+    // The budget runs out before the rest of the comments can be fetched.
+    let fixtures = Fixtures::new()
+        .respond("issues(first: 1)", fixtures::sizes(1, 0))
+        .respond("issues(", page(3));
+    let client = github::Client::github("fake")
+        .with_transport(fixtures)
+        .with_budget(5);
+
+    let threads = issues(
+        &client,
+        &None,
+        &Mode::Issues,
+        "a",
+        "b",
+        &ProgressBar::hidden(),
+    )
+    .unwrap();
+    assert_eq!(1, threads[0].comments.edges.len());
+    assert!(!threads[0].comments.followed_up);
+}
+
+#[test]
+fn review_comment_counts() {
+    use crate::fixtures;
+    use crate::transport::Fixtures;
+    use serde_json::json;
+
+    let review = |id: &str, state: &str, at: Option<&str>| {
+        json!({"node": {"id": id, "author": {"login": "bob"}, "authorAssociation": "MEMBER",
+          "state": state, "submittedAt": at}})
+    };
+    let mut thread = fixtures::thread(1, "2020-01-01T00:00:00Z", "2020-01-01T00:00:00Z");
+    thread["node"]["reviews"] = json!({"edges": [
+        review("R1", "COMMENTED", Some("2020-01-02T00:00:00Z")),
+        review("R2", "PENDING", None),
+    ]});
+    let counts = json!({"data": {"nodes": [{"id": "R1", "comments": {"totalCount": 4}}]}});
+
+    // Only the submitted review is asked about.
+    let fixtures = Fixtures::new()
+        .respond("issues(first: 1)", fixtures::sizes(0, 1))
+        .respond(
+            "pullRequests(",
+            fixtures::page("pullRequests", None, &[thread]),
+        )
+        .respond("\"ids\":[\"R1\"]", counts.to_string());
+    let client = github::Client::github("fake").with_transport(fixtures);
+
+    let threads = issues(&client, &None, &Mode::PRs, "a", "b", &ProgressBar::hidden()).unwrap();
+    let counted: Vec<usize> = threads[0]
+        .reviews
+        .iter()
+        .flat_map(|c| c.edges.iter().map(|n| n.node.comment_count))
+        .collect();
+    assert_eq!(vec![4, 0], counted);
+}
+
+#[test]
+fn page_costs() {
+    // The page itself, then the reactors of each of 8 reaction groups, the
+    // labels and the comments of every thread.
+    assert_eq!(1 + 100 * (8 + 1 + 1), Mode::Issues.page_requests(100));
+    // Reviews too.
+    assert_eq!(1 + 100 * (8 + 1 + 1 + 1), Mode::PRs.page_requests(100));
+    assert_eq!(1 + 5 * (8 + 1 + 1 + 1), Mode::PRs.page_requests(5));
+
+    // What naming the reactors of comments would add, as noted on `Comment`.
+    let reacted = ISSUES_QUERY.replacen(
+        "authorAssociation",
+        "authorAssociation reactionGroups { reactors(first: 1) { totalCount } }",
+        1,
+    );
+    assert_eq!(
+        80_000,
+        cost::requests(&reacted, 100) - Mode::Issues.page_requests(100)
+    );
+
+    // What counting review comments within each page would add, as noted on
+    // `REVIEW_COMMENTS_QUERY`, against a follow-up per hundred reviews.
+    let commented = PRS_QUERY.replacen(
+        "submittedAt",
+        "submittedAt comments(first: 100) { totalCount }",
+        1,
+    );
+    assert_eq!(
+        10_000,
+        cost::requests(&commented, 100) - Mode::PRs.page_requests(100)
+    );
+    assert_eq!(1, cost::points(cost::requests(REVIEW_COMMENTS_QUERY, 100)));
+}
+
+#[test]
+fn unknown_values() {
+    let reasons: Vec<StateReason> =
+        serde_json::from_str(r#"["NOT_PLANNED", "SOMETHING_NEW"]"#).unwrap();
+    assert_eq!(vec![StateReason::NotPlanned, StateReason::Unknown], reasons);
+
+    let reactions: Vec<Reaction> = serde_json::from_str(r#"["HEART", "SALUTE"]"#).unwrap();
+    assert_eq!(vec![Reaction::Heart, Reaction::Unknown], reactions);
+
+    let states: Vec<ReviewState> = serde_json::from_str(r#"["APPROVED", "BLESSED"]"#).unwrap();
+    assert_eq!(vec![ReviewState::Approved, ReviewState::Unknown], states);
+}
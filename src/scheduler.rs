@@ -0,0 +1,137 @@
+//! Bounding and pacing the requests made by every crawl of a run.
+
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How many requests may be in flight at once, unless told otherwise.
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+struct State {
+    in_flight: usize,
+    /// The earliest time the next request may be sent.
+    next: Instant,
+}
+
+/// Shared by all clones of a [`Client`](struct.Client.html), so that however
+/// many repositories are being crawled at once, Github only ever sees a
+/// limited number of simultaneous requests, started no faster than some rate.
+pub struct Scheduler {
+    limit: usize,
+    interval: Duration,
+    state: Mutex<State>,
+    freed: Condvar,
+}
+
+impl Default for Scheduler {
+    fn default() -> Scheduler {
+        Scheduler::new(DEFAULT_CONCURRENCY)
+    }
+}
+
+impl Scheduler {
+    /// Allow at most `limit` requests in flight at once.
+    pub fn new(limit: usize) -> Scheduler {
+        Scheduler {
+            limit: limit.max(1),
+            interval: Duration::from_secs(0),
+            state: Mutex::new(State {
+                in_flight: 0,
+                next: Instant::now(),
+            }),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Start at most this many requests per second.
+    pub fn per_second(self, rate: u32) -> Scheduler {
+        Scheduler {
+            interval: Duration::from_secs(1) / rate.max(1),
+            ..self
+        }
+    }
+
+    /// Roughly how long it takes to let `requests` through, if each takes
+    /// `latency` to answer.
+    pub(crate) fn duration(&self, requests: u32, latency: Duration) -> Duration {
+        let waves = requests.div_ceil(self.limit as u32);
+        (latency * waves).max(self.interval * requests)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // The state is always left consistent, so a panic elsewhere is no
+        // reason to stop scheduling.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Wait for a turn to send a request. The turn ends when the `Permit` is
+    /// dropped.
+    pub(crate) fn acquire(&self) -> Permit<'_> {
+        let mut state = self.lock();
+        while state.in_flight >= self.limit {
+            state = self.freed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+
+        state.in_flight += 1;
+        let now = Instant::now();
+        let start = state.next.max(now);
+        state.next = start + self.interval;
+        drop(state);
+
+        if start > now {
+            thread::sleep(start - now);
+        }
+
+        Permit { scheduler: self }
+    }
+
+    /// Hold back every request for a while, say because Github has asked us
+    /// to slow down.
+    pub(crate) fn pause(&self, delay: Duration) {
+        let mut state = self.lock();
+        state.next = state.next.max(Instant::now() + delay);
+    }
+}
+
+/// A turn to send a request.
+pub(crate) struct Permit<'a> {
+    scheduler: &'a Scheduler,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.scheduler.lock().in_flight -= 1;
+        self.scheduler.freed.notify_one();
+    }
+}
+
+#[test]
+fn bounded_and_paced() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let scheduler = Arc::new(Scheduler::new(2));
+    let active = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let (scheduler, active, peak) = (scheduler.clone(), active.clone(), peak.clone());
+            thread::spawn(move || {
+                let _permit = scheduler.acquire();
+                let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(10));
+                active.fetch_sub(1, Ordering::SeqCst);
+            })
+        })
+        .collect();
+    handles.into_iter().for_each(|h| h.join().unwrap());
+    assert_eq!(2, peak.load(Ordering::SeqCst));
+
+    let paced = Scheduler::new(10).per_second(50);
+    let start = Instant::now();
+    for _ in 0..5 {
+        paced.acquire();
+    }
+    assert!(start.elapsed() >= Duration::from_millis(80));
+}
@@ -0,0 +1,67 @@
+//! Page sizes that adapt to how heavy Github finds a query.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Grow a page size again after this many pages in a row succeed.
+pub(crate) const GROW_AFTER: u32 = 10;
+
+/// The page size that last worked for each kind of query, like the Issues of a
+/// particular repository. Optionally kept on disk, so later runs needn't
+/// rediscover them.
+#[derive(Default)]
+pub struct PageSizes {
+    path: Option<PathBuf>,
+    sizes: Mutex<BTreeMap<String, u32>>,
+}
+
+impl PageSizes {
+    /// Remembered for this run only.
+    pub fn new() -> PageSizes {
+        PageSizes::default()
+    }
+
+    /// Remembered in a file, which is read now and updated as sizes change.
+    pub fn load(path: PathBuf) -> PageSizes {
+        let sizes = fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+
+        PageSizes {
+            path: Some(path),
+            sizes: Mutex::new(sizes),
+        }
+    }
+
+    /// The remembered size for some kind of query.
+    pub fn get(&self, key: &str) -> Option<u32> {
+        self.sizes.lock().ok()?.get(key).copied()
+    }
+
+    /// Remember a size that worked for some kind of query.
+    pub(crate) fn set(&self, key: &str, size: u32) {
+        let json = match self.sizes.lock() {
+            Err(_) => return,
+            Ok(mut sizes) => {
+                if sizes.insert(key.to_string(), size) == Some(size) {
+                    return;
+                }
+                serde_json::to_vec_pretty(&*sizes)
+            }
+        };
+
+        // Like the cache, failing to save this shouldn't fail the query.
+        if let (Some(path), Ok(json)) = (self.path.as_ref(), json) {
+            let tmp = path.with_extension("tmp");
+            let _ = path
+                .parent()
+                .map(fs::create_dir_all)
+                .unwrap_or(Ok(()))
+                .and_then(|_| fs::write(&tmp, json))
+                .and_then(|_| fs::rename(&tmp, path));
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! A local copy of a repository's Issues and Pull Requests, for the `sync`
+//! command.
+
+use crate::github;
+use crate::repo::{self, Mode};
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Every thread of a single repository, keyed by their number.
+#[derive(Default, Deserialize, Serialize)]
+pub struct Store {
+    /// When the last successful sync began.
+    pub synced_at: Option<DateTime<Utc>>,
+    pub issues: BTreeMap<u32, repo::Issue>,
+    pub prs: BTreeMap<u32, repo::Issue>,
+}
+
+impl Store {
+    /// Where the store of a given repository lives within some directory.
+    pub fn path(dir: &Path, host: &str, owner: &str, repo: &str) -> PathBuf {
+        dir.join(host).join(owner).join(format!("{}.json", repo))
+    }
+
+    /// Read a store from disk, or start a fresh one if there isn't one yet.
+    pub fn load(path: &Path) -> anyhow::Result<Store> {
+        if path.exists() {
+            let store = serde_json::from_slice(&fs::read(path)?)?;
+            Ok(store)
+        } else {
+            Ok(Store::default())
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(self)?)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// Fetch every thread that has changed since the last sync. Yields the
+    /// number of Issues and Pull Requests that were updated. Fails, leaving the
+    /// store as it was, if paging had to stop early.
+    pub fn sync(
+        &mut self,
+        client: &github::Client,
+        owner: &str,
+        repo: &str,
+    ) -> anyhow::Result<(usize, usize)> {
+        // Cached pages would hide recent updates.
+        let client = client.clone().without_cache();
+        let began = Utc::now();
+
+        let issues = repo::updated_since(&client, &self.synced_at, &Mode::Issues, owner, repo)?;
+        let prs =
+            repo::updated_since(&client, &self.synced_at, &Mode::PRsWithCommits, owner, repo)?;
+        let counts = (issues.len(), prs.len());
+
+        // Paging ends quietly when interrupted or over budget. Were this then
+        // taken as a full sync, the threads not yet reached would never be
+        // fetched again.
+        if client.stopped_early() {
+            return Err(anyhow!(
+                "Stopped before {}/{} was fully synced, so nothing was saved.",
+                owner,
+                repo
+            ));
+        }
+
+        self.issues
+            .extend(issues.into_iter().map(|i| (i.number, i)));
+        self.prs.extend(prs.into_iter().map(|i| (i.number, i)));
+        self.synced_at = Some(began);
+
+        Ok(counts)
+    }
+}
+
+#[test]
+fn incremental_sync() {
+    use crate::fixtures;
+    use crate::transport::Fixtures;
+
+    let page = |kind: &str, number: u32, updated: &str| {
+        fixtures::page(
+            kind,
+            Some("next"),
+            &[fixtures::thread(number, "2021-01-01T00:00:00Z", updated)],
+        )
+    };
+
+    // Since the only Issue was last updated before the previous sync, there's
+    // no need to page any further.
+    let client = github::Client::github("fake").with_transport(
+        Fixtures::new()
+            .respond("issues(", page("issues", 1, "2021-01-02T00:00:00Z"))
+            .respond(
+                "pullRequests(",
+                page("pullRequests", 7, "2021-03-01T00:00:00Z"),
+            )
+            .respond(
+                "pullRequests(",
+                page("pullRequests", 5, "2021-01-02T00:00:00Z"),
+            ),
+    );
+
+    let mut store = Store {
+        synced_at: "2021-02-01T00:00:00Z".parse().ok(),
+        ..Store::default()
+    };
+
+    assert_eq!((0, 1), store.sync(&client, "a", "b").unwrap());
+    assert!(store.prs.contains_key(&7));
+    assert!(store.synced_at.unwrap() > Utc::now() - chrono::Duration::minutes(1));
+}
+
+#[test]
+fn truncated_sync() {
+    use crate::fixtures;
+    use crate::transport::Fixtures;
+
+    let page = fixtures::costly_page(
+        3,
+        "issues",
+        Some("next"),
+        &[fixtures::thread(
+            1,
+            "2021-01-01T00:00:00Z",
+            "2021-03-01T00:00:00Z",
+        )],
+    );
+    let synced_at = "2021-02-01T00:00:00Z".parse().ok();
+    let mut store = Store {
+        synced_at,
+        ..Store::default()
+    };
+
+    // Ctrl-C during a sync.
+    let client = github::Client::github("fake").with_transport(Fixtures::new());
+    client.interrupt();
+    assert!(store.sync(&client, "a", "b").is_err());
+    assert_eq!(synced_at, store.synced_at);
+
+    // Only the first page fits within the budget.
+    let client = github::Client::github("fake")
+        .with_transport(Fixtures::new().respond("issues(", page))
+        .with_budget(5);
+    assert!(store.sync(&client, "a", "b").is_err());
+    assert_eq!(synced_at, store.synced_at);
+    assert!(store.issues.is_empty());
+}
@@ -0,0 +1,124 @@
+//! A pool of access tokens, rotated as their quotas run low.
+
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Move on to the next token once the current one has fewer API points than
+/// this remaining.
+pub const ROTATE_BELOW: u32 = 100;
+
+/// A token and what we last heard about its quota.
+struct Slot {
+    token: String,
+    remaining: Option<u32>,
+    /// When the quota resets, in seconds since the epoch.
+    reset: Option<u64>,
+}
+
+impl Slot {
+    fn is_usable(&self, now: u64) -> bool {
+        match (self.remaining, self.reset) {
+            (Some(r), _) if r >= ROTATE_BELOW => true,
+            (Some(_), Some(reset)) => reset <= now,
+            (Some(_), None) => false,
+            (None, _) => true,
+        }
+    }
+}
+
+struct Pool {
+    slots: Vec<Slot>,
+    current: usize,
+}
+
+impl Pool {
+    /// Switch to the next usable token after the current one, if any.
+    fn rotate(&mut self) {
+        let now = now();
+        let len = self.slots.len();
+        if let Some(next) = (1..len)
+            .map(|i| (self.current + i) % len)
+            .find(|i| self.slots[*i].is_usable(now))
+        {
+            self.current = next;
+        }
+    }
+}
+
+/// One or more tokens to authenticate with. Only one is in use at a time.
+pub struct Tokens {
+    pool: Mutex<Pool>,
+}
+
+impl Tokens {
+    pub fn new(tokens: Vec<String>) -> Tokens {
+        let slots = tokens
+            .into_iter()
+            .map(|token| Slot {
+                token,
+                remaining: None,
+                reset: None,
+            })
+            .collect();
+
+        Tokens {
+            pool: Mutex::new(Pool { slots, current: 0 }),
+        }
+    }
+
+    /// The token to use for the next request, and its position in the pool.
+    pub fn current(&self) -> (usize, String) {
+        self.pool
+            .lock()
+            .ok()
+            .and_then(|p| p.slots.get(p.current).map(|s| (p.current, s.token.clone())))
+            .unwrap_or_default()
+    }
+
+    /// Record what a response said about a token's quota, rotating to another
+    /// token if it's running low.
+    pub fn observe(&self, ix: usize, remaining: u32, reset: Option<u64>) {
+        if let Ok(mut pool) = self.pool.lock() {
+            if let Some(slot) = pool.slots.get_mut(ix) {
+                slot.remaining = Some(remaining);
+                slot.reset = reset.or(slot.reset);
+            }
+            if ix == pool.current && remaining < ROTATE_BELOW {
+                pool.rotate();
+            }
+        }
+    }
+
+    /// A token's quota has run out. Yields `true` if there is another token
+    /// to switch to, in which case the request can be retried immediately.
+    pub fn exhausted(&self, ix: usize, reset: Option<u64>) -> bool {
+        self.observe(ix, 0, reset);
+        self.pool.lock().map(|p| p.current != ix).unwrap_or(false)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[test]
+fn rotation() {
+    let tokens = Tokens::new(vec!["a".to_string(), "b".to_string(), "c".to_string()]);
+    assert_eq!((0, "a".to_string()), tokens.current());
+
+    tokens.observe(0, 500, None);
+    assert_eq!(0, tokens.current().0);
+
+    tokens.observe(0, 50, Some(now() + 3600));
+    assert_eq!(1, tokens.current().0);
+
+    assert!(tokens.exhausted(1, Some(now() + 3600)));
+    assert_eq!(2, tokens.current().0);
+
+    // Nothing left to switch to.
+    assert!(!tokens.exhausted(2, Some(now() + 3600)));
+    assert_eq!(2, tokens.current().0);
+}
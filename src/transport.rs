@@ -0,0 +1,501 @@
+//! Delivery of requests to Github, and fixtures to stand in for it.
+
+use crate::cache::fingerprint;
+use anyhow::{anyhow, Context};
+use curl::easy::{Easy, HttpVersion, List};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The raw result of a single HTTP exchange.
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub code: u32,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// A successful response with the given body and no headers.
+    pub fn ok(body: impl Into<Vec<u8>>) -> Response {
+        Response {
+            code: 200,
+            headers: vec![],
+            body: body.into(),
+        }
+    }
+
+    /// The value of some header, if it was sent. Names are case-insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The body as text, for error messages and the like.
+    pub fn text(&self) -> &str {
+        std::str::from_utf8(&self.body).unwrap_or("<binary response>")
+    }
+}
+
+/// A means of POSTing a request body to some URL.
+///
+/// `headers` are full header lines, like `user-agent: credit`.
+pub trait Transport: Send + Sync {
+    fn post(&self, url: &str, headers: &[String], body: &[u8]) -> anyhow::Result<Response>;
+}
+
+/// Settings for how connections to Github are made.
+#[derive(Debug, Clone)]
+pub struct Network {
+    /// A proxy to send all requests through, like `http://proxy:3128`.
+    pub proxy: Option<String>,
+    /// Comma-separated hosts which shouldn't be proxied.
+    pub no_proxy: Option<String>,
+    /// A file of CA certificates to verify Github's certificate with.
+    pub ca_bundle: Option<PathBuf>,
+    /// Give up if a connection can't be established within this time.
+    pub connect_timeout: Option<Duration>,
+    /// Give up if an entire request takes longer than this.
+    pub timeout: Option<Duration>,
+    /// Abort a transfer slower than this many bytes per second...
+    pub low_speed_limit: u32,
+    /// ...for this long.
+    pub low_speed_time: Duration,
+}
+
+impl Default for Network {
+    /// Sensible defaults that prevent a stalled connection from hanging
+    /// forever.
+    fn default() -> Self {
+        Network {
+            proxy: None,
+            no_proxy: None,
+            ca_bundle: None,
+            connect_timeout: Some(Duration::from_secs(30)),
+            timeout: None,
+            low_speed_limit: 1,
+            low_speed_time: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Real network access via `curl`. Handles are kept between requests, so that
+/// connections to Github are reused rather than set up anew each time.
+#[derive(Default)]
+pub struct Curl {
+    network: Network,
+    /// Print the timing of each request to stderr.
+    verbose: bool,
+    /// Idle handles, each holding its own open connections.
+    handles: Mutex<Vec<Easy>>,
+    stats: Mutex<Stats>,
+}
+
+impl Curl {
+    pub fn new(network: Network) -> Curl {
+        Curl {
+            network,
+            ..Curl::default()
+        }
+    }
+
+    /// Print the timing of each request to stderr.
+    pub fn verbose(self, verbose: bool) -> Curl {
+        Curl { verbose, ..self }
+    }
+
+    /// Where the time has gone in requests made so far.
+    pub fn stats(&self) -> Stats {
+        self.stats.lock().map(|s| s.clone()).unwrap_or_default()
+    }
+
+    fn configure(&self, handle: &mut Easy) -> Result<(), curl::Error> {
+        let net = &self.network;
+
+        if let Some(proxy) = &net.proxy {
+            handle.proxy(proxy)?;
+        }
+        if let Some(no_proxy) = &net.no_proxy {
+            handle.noproxy(no_proxy)?;
+        }
+        if let Some(ca) = &net.ca_bundle {
+            handle.cainfo(ca)?;
+        }
+        if let Some(t) = net.connect_timeout {
+            handle.connect_timeout(t)?;
+        }
+        if let Some(t) = net.timeout {
+            handle.timeout(t)?;
+        }
+        handle.low_speed_limit(net.low_speed_limit)?;
+        handle.low_speed_time(net.low_speed_time)?;
+
+        // An empty string asks for every encoding curl can decode, which it
+        // then does transparently.
+        handle.accept_encoding("")?;
+        handle.tcp_keepalive(true)?;
+        if curl::Version::get().feature_http2() {
+            handle.http_version(HttpVersion::V2TLS)?;
+        }
+
+        Ok(())
+    }
+
+    /// An idle handle, or a new one if all are busy.
+    fn handle(&self) -> Result<Easy, curl::Error> {
+        match self.handles.lock().ok().and_then(|mut hs| hs.pop()) {
+            Some(handle) => Ok(handle),
+            None => {
+                let mut handle = Easy::new();
+                self.configure(&mut handle)?;
+                Ok(handle)
+            }
+        }
+    }
+
+    fn record(&self, url: &str, code: u32, handle: &mut Easy) -> Result<(), curl::Error> {
+        let connects = handle.num_connects()? as u32;
+        let connecting = handle.appconnect_time()?.max(handle.connect_time()?);
+        let total = handle.total_time()?;
+        let waiting = handle.starttransfer_time()?.saturating_sub(connecting);
+        let bytes = handle.download_size()? as u64;
+
+        if self.verbose {
+            eprintln!(
+                "POST {}: HTTP {} in {}ms ({}ms connecting, {}ms waiting, {} bytes{})",
+                url,
+                code,
+                total.as_millis(),
+                connecting.as_millis(),
+                waiting.as_millis(),
+                bytes,
+                if connects > 0 { ", new connection" } else { "" }
+            );
+        }
+
+        if let Ok(mut stats) = self.stats.lock() {
+            stats.requests += 1;
+            stats.connections += connects;
+            stats.connecting += connecting;
+            stats.waiting += waiting;
+            stats.total += total;
+            stats.bytes += bytes;
+        }
+
+        Ok(())
+    }
+}
+
+/// Where the time went in requests made through [`Curl`](struct.Curl.html).
+#[derive(Debug, Default, Clone)]
+pub struct Stats {
+    pub requests: u32,
+    /// How many new connections had to be opened.
+    pub connections: u32,
+    /// Time spent establishing connections, including TLS handshakes.
+    pub connecting: Duration,
+    /// Time spent waiting for Github to start responding.
+    pub waiting: Duration,
+    pub total: Duration,
+    /// Bytes received, before decompression.
+    pub bytes: u64,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} requests over {} connections in {:.1}s ({:.1}s connecting, {:.1}s waiting on Github), {} KiB received",
+            self.requests,
+            self.connections,
+            self.total.as_secs_f64(),
+            self.connecting.as_secs_f64(),
+            self.waiting.as_secs_f64(),
+            self.bytes / 1024
+        )
+    }
+}
+
+impl Transport for Curl {
+    fn post(&self, url: &str, headers: &[String], body: &[u8]) -> anyhow::Result<Response> {
+        let mut handle = self.handle()?;
+        let mut resp: Vec<u8> = Vec::new();
+        let mut resp_headers: Vec<(String, String)> = Vec::new();
+        handle.url(url)?;
+        handle.post(true)?;
+        handle.post_field_size(body.len() as u64)?;
+
+        // --- Add Headers --- //
+        let mut list = List::new();
+        for h in headers {
+            list.append(h)?;
+        }
+        handle.http_headers(list)?;
+
+        // Blocked off to allow `resp` and `resp_headers` to be borrowed
+        // immutably below.
+        {
+            let mut bytes = body;
+            let mut tx = handle.transfer();
+            tx.read_function(move |buf| Ok(bytes.read(buf).unwrap_or(0)))?;
+            tx.header_function(|line| {
+                if let Some((k, v)) = std::str::from_utf8(line)
+                    .ok()
+                    .and_then(|l| l.split_once(':'))
+                {
+                    resp_headers.push((k.trim().to_string(), v.trim().to_string()));
+                }
+                true
+            })?;
+            tx.write_function(|data| {
+                resp.extend_from_slice(data);
+                Ok(data.len())
+            })?;
+            tx.perform()?;
+        }
+
+        let code = handle.response_code()?;
+        self.record(url, code, &mut handle)?;
+
+        // Only handles that finished cleanly are kept for reuse.
+        if let Ok(mut handles) = self.handles.lock() {
+            handles.push(handle);
+        }
+
+        Ok(Response {
+            code,
+            headers: resp_headers,
+            body: resp,
+        })
+    }
+}
+
+/// Canned responses, for running without network access.
+///
+/// Each fixture is paired with a pattern. A request is answered by the first
+/// remaining fixture whose pattern appears somewhere in the request body, and
+/// that fixture is then used up. Successive pages of the same query can thus
+/// be given in order under the same pattern.
+#[derive(Default)]
+pub struct Fixtures {
+    responses: Mutex<Vec<(String, Response)>>,
+}
+
+impl Fixtures {
+    pub fn new() -> Fixtures {
+        Fixtures::default()
+    }
+
+    /// Answer a request whose body contains `pattern` with a successful
+    /// response.
+    pub fn respond(self, pattern: &str, body: impl Into<Vec<u8>>) -> Fixtures {
+        self.respond_with(pattern, Response::ok(body))
+    }
+
+    /// Answer a request whose body contains `pattern` with some arbitrary
+    /// response.
+    pub fn respond_with(self, pattern: &str, response: Response) -> Fixtures {
+        if let Ok(mut rs) = self.responses.lock() {
+            rs.push((pattern.to_string(), response));
+        }
+        self
+    }
+
+    /// How many fixtures have yet to be used?
+    pub fn remaining(&self) -> usize {
+        self.responses.lock().map(|rs| rs.len()).unwrap_or(0)
+    }
+}
+
+impl Transport for Fixtures {
+    fn post(&self, _: &str, _: &[String], body: &[u8]) -> anyhow::Result<Response> {
+        let body = String::from_utf8_lossy(body);
+        let mut responses = self
+            .responses
+            .lock()
+            .map_err(|_| anyhow!("Fixtures were poisoned."))?;
+        let ix = responses
+            .iter()
+            .position(|(pattern, _)| body.contains(pattern.as_str()))
+            .ok_or_else(|| anyhow!("No fixture matches the request:\n{}", body))?;
+
+        Ok(responses.remove(ix).1)
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    fn post(&self, url: &str, headers: &[String], body: &[u8]) -> anyhow::Result<Response> {
+        (**self).post(url, headers, body)
+    }
+}
+
+/// A single request and its response, as written to disk by a
+/// [`Recorder`](struct.Recorder.html). Bodies that are JSON are kept as JSON,
+/// so that recordings can be read and trimmed by hand.
+#[derive(Serialize, Deserialize)]
+struct Exchange {
+    url: String,
+    request: Value,
+    code: u32,
+    headers: Vec<(String, String)>,
+    response: Value,
+}
+
+/// Where the exchange for some request lives within a recording.
+fn exchange_path(dir: &Path, url: &str, body: &[u8]) -> PathBuf {
+    let key = fingerprint(&[url.as_bytes(), body]);
+    dir.join(format!("{:016x}.json", key))
+}
+
+fn to_value(bytes: &[u8]) -> Value {
+    serde_json::from_slice(bytes)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(bytes).into_owned()))
+}
+
+/// Recordings end up attached to bug reports, so the installation tokens
+/// handed out to Github Apps mustn't be written into them. Replaying doesn't
+/// need the real thing.
+fn redacted(mut value: Value) -> Value {
+    if let Some(token) = value.get_mut("token").filter(|t| t.is_string()) {
+        *token = Value::String("REDACTED".to_string());
+    }
+    value
+}
+
+fn from_value(value: Value) -> Vec<u8> {
+    match value {
+        Value::String(s) => s.into_bytes(),
+        v => v.to_string().into_bytes(),
+    }
+}
+
+/// Passes requests on to another `Transport`, and writes every exchange into a
+/// directory for [`Replay`](struct.Replay.html) to serve back later. Should the
+/// same request be made twice, the last response wins.
+pub struct Recorder<T> {
+    dir: PathBuf,
+    inner: T,
+}
+
+impl<T: Transport> Recorder<T> {
+    pub fn new(dir: PathBuf, inner: T) -> anyhow::Result<Recorder<T>> {
+        fs::create_dir_all(&dir).with_context(|| format!("Couldn't create {}", dir.display()))?;
+        Ok(Recorder { dir, inner })
+    }
+}
+
+impl<T: Transport> Transport for Recorder<T> {
+    fn post(&self, url: &str, headers: &[String], body: &[u8]) -> anyhow::Result<Response> {
+        let resp = self.inner.post(url, headers, body)?;
+        let exchange = Exchange {
+            url: url.to_string(),
+            request: to_value(body),
+            code: resp.code,
+            headers: resp.headers.clone(),
+            response: redacted(to_value(&resp.body)),
+        };
+
+        // Written whole and then moved, as requests may be made in parallel.
+        let path = exchange_path(&self.dir, url, body);
+        let tmp = path.with_extension(format!("tmp{:?}", std::thread::current().id()));
+        fs::write(&tmp, serde_json::to_vec_pretty(&exchange)?)?;
+        fs::rename(&tmp, &path)?;
+        Ok(resp)
+    }
+}
+
+/// Answers requests from a directory written by a
+/// [`Recorder`](struct.Recorder.html), never touching the network.
+pub struct Replay {
+    dir: PathBuf,
+}
+
+impl Replay {
+    pub fn new(dir: PathBuf) -> Replay {
+        Replay { dir }
+    }
+}
+
+impl Transport for Replay {
+    fn post(&self, url: &str, _: &[String], body: &[u8]) -> anyhow::Result<Response> {
+        let path = exchange_path(&self.dir, url, body);
+        let bytes = fs::read(&path).with_context(|| {
+            format!(
+                "No recorded response in {} for the request:\n{}",
+                self.dir.display(),
+                String::from_utf8_lossy(body)
+            )
+        })?;
+        let exchange: Exchange = serde_json::from_slice(&bytes)
+            .with_context(|| format!("Malformed recording {}", path.display()))?;
+
+        Ok(Response {
+            code: exchange.code,
+            headers: exchange.headers,
+            body: from_value(exchange.response),
+        })
+    }
+}
+
+#[test]
+fn record_and_replay() {
+    let dir = std::env::temp_dir().join(format!("credit-record-test-{}", std::process::id()));
+    let fixtures = Fixtures::new()
+        .respond("first", r#"{"data": 1}"#)
+        .respond_with(
+            "second",
+            Response {
+                code: 502,
+                headers: vec![("Retry-After".to_string(), "1".to_string())],
+                body: b"Bad Gateway".to_vec(),
+            },
+        );
+    let recorder = Recorder::new(dir.clone(), fixtures).unwrap();
+    recorder.post("url", &[], b"first").unwrap();
+    recorder.post("url", &[], b"second").unwrap();
+
+    let replay = Replay::new(dir.clone());
+    let first = replay.post("url", &[], b"first").unwrap();
+    assert_eq!(200, first.code);
+    assert_eq!(r#"{"data":1}"#, first.text());
+
+    let second = replay.post("url", &[], b"second").unwrap();
+    assert_eq!(502, second.code);
+    assert_eq!(Some("1"), second.header("retry-after"));
+    assert_eq!("Bad Gateway", second.text());
+
+    assert!(replay.post("url", &[], b"third").is_err());
+    assert!(replay.post("other", &[], b"first").is_err());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn tokens_not_recorded() {
+    let dir = std::env::temp_dir().join(format!("credit-redact-test-{}", std::process::id()));
+    let fixtures = Fixtures::new().respond(
+        "",
+        r#"{"token": "ghs_secret", "expires_at": "2021-01-01T01:00:00Z"}"#,
+    );
+    let recorder = Recorder::new(dir.clone(), fixtures).unwrap();
+    let live = recorder.post("access_tokens", &[], b"").unwrap();
+    assert!(live.text().contains("ghs_secret"));
+
+    let recorded = fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| fs::read_to_string(e.unwrap().path()).unwrap())
+        .collect::<String>();
+    assert!(!recorded.contains("ghs_secret"));
+
+    let replayed = Replay::new(dir.clone())
+        .post("access_tokens", &[], b"")
+        .unwrap();
+    assert!(replayed.text().contains("2021-01-01T01:00:00Z"));
+    fs::remove_dir_all(dir).unwrap();
+}